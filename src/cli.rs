@@ -1,24 +1,141 @@
-use apple_navidrome_lib::{config::Config, navidrome_writer::NavidromeWriter, structs::Library};
+use std::{
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::Duration,
+};
+
+use apple_navidrome_lib::{
+    config::{self, Config},
+    navidrome_writer::{
+        dates::write_date_changes, reconcile::write_apple_changes, snapshot::SyncSnapshot,
+        tags::write_tag_differences, NavidromeWriter,
+    },
+    report::{PlaylistExport, RunReport},
+    structs::{
+        checks::count_warnings,
+        listening::ListeningReport,
+        query::{Grouping, Measure, Sort},
+        track::Track,
+        Library,
+    },
+    xml_reader::{err::LibraryXmlReader, Diagnostic},
+};
+use clap::{Parser, Subcommand};
 
 /*
 Notes on fields:
 
 - size is not consistent between navidrome and apple music
 - navidrome does not consistenly assign a track number if a number is not given (both 0 and 1 observed)
-- things break if ';' is in an artist, both for queries and apple music
+- artists credited together (e.g. "Band feat. Guest") are split for artist play counts, but tracks are still matched on the whole credit
  */
 
 pub mod err {
-    use apple_navidrome_lib::xml_reader;
+    use std::{path::PathBuf, process::ExitCode};
+
+    use apple_navidrome_lib::{config, navidrome_writer, xml_reader};
+    use chrono::{DateTime, Utc};
 
     #[derive(Debug)]
     pub enum Cli {
+        Config(config::err::Config),
+        ConfigExists {
+            path: PathBuf,
+        },
         LibraryXmlReader(xml_reader::err::LibraryXmlReader),
-        NavidromeSql(rusqlite::Error),
+        NavidromeWriter(navidrome_writer::err::NavidromeWriter),
+        DatabaseCopy {
+            error: std::io::Error,
+        },
+        /// The library is older than the one recorded by the last incremental sync.
+        OlderLibrary {
+            library_date: DateTime<Utc>,
+            snapshot_date: DateTime<Utc>,
+        },
+        Invalid,
         Json(serde_json::Error),
         Io(std::io::Error),
     }
 
+    impl Cli {
+        /// The exit code for the error, distinct for each kind of problem.
+        ///
+        /// Code 2 is left for invalid arguments, as used by clap.
+        pub fn exit_code(&self) -> ExitCode {
+            match self {
+                Cli::Config(_) | Cli::ConfigExists { .. } => ExitCode::from(3),
+                Cli::LibraryXmlReader(_) | Cli::OlderLibrary { .. } => ExitCode::from(4),
+                Cli::NavidromeWriter(_) | Cli::DatabaseCopy { .. } => ExitCode::from(5),
+                Cli::Invalid => ExitCode::from(6),
+                Cli::Json(_) | Cli::Io(_) => ExitCode::from(1),
+            }
+        }
+
+        pub fn log(&self) {
+            match self {
+                Cli::Config(config::err::Config::Missing { .. }) => {}
+                Cli::Config(config::err::Config::Io { path, error }) => {
+                    log::error!("There's some issue reading the config file {path:?}, perhaps delete it and try again?");
+                    log::error!("Error: {error:?}");
+                }
+                Cli::Config(config::err::Config::Create { path, error }) => {
+                    log::error!("Could not create a default config file at {path:?}.");
+                    log::error!("Error: {error:?}");
+                }
+                Cli::Config(e) => {
+                    log::error!("There was a problem reading the configuration file.");
+                    log::error!(
+                        "Try fixing the file, or deleting it and running this program again."
+                    );
+                    log::error!("Error details: {e:?}");
+                }
+                Cli::ConfigExists { path } => {
+                    log::error!(
+                        "A config file already exists at {path:?}, use --force to replace it."
+                    );
+                }
+                Cli::NavidromeWriter(navidrome_writer::err::NavidromeWriter::NoUser { user }) => {
+                    log::error!("No user \"{user}\" found.");
+                }
+                Cli::NavidromeWriter(navidrome_writer::err::NavidromeWriter::MultipleUsers {
+                    user,
+                    ..
+                }) => {
+                    log::error!("Multiple ids found for user \"{user}\".");
+                    log::error!("Please add a database id to the config file");
+                    log::error!("For example, a line which reads (though your ):");
+                    log::error!("navidrome_user_id = \"d868f4b6-1d16-4c05-ae0c-4aca4ef42788\"");
+                }
+                Cli::DatabaseCopy { error } => {
+                    log::error!("Failed to create a copy of the navidrome database for export");
+                    log::error!("Exiting without any further action.");
+                    log::error!("{error:?}");
+                }
+                Cli::OlderLibrary {
+                    library_date,
+                    snapshot_date,
+                } => {
+                    log::error!("The library is from {library_date}, but the last sync was of a library from {snapshot_date}.");
+                    log::error!("An incremental sync would undo plays made since, so either export the library again, or set incremental_sync = false.");
+                }
+                Cli::LibraryXmlReader(xml_reader::err::LibraryXmlReader::Value {
+                    position,
+                    key,
+                    error,
+                }) => {
+                    match position {
+                        Some(position) => log::error!("The value of \"{key}\" at {position} in the library could not be read."),
+                        None => log::error!("The value of \"{key}\" in the library could not be read."),
+                    }
+                    log::error!("Fix the library, or pass --lenient to skip such values.");
+                    log::error!("Error details: {error:?}");
+                }
+                Cli::Invalid => {}
+                e => log::error!("{e:?}"),
+            }
+        }
+    }
+
     impl From<std::io::Error> for Cli {
         fn from(error: std::io::Error) -> Self {
             Cli::Io(error)
@@ -31,88 +148,824 @@ pub mod err {
         }
     }
 
+    impl From<config::err::Config> for Cli {
+        fn from(error: config::err::Config) -> Self {
+            Cli::Config(error)
+        }
+    }
+
     impl From<xml_reader::err::LibraryXmlReader> for Cli {
         fn from(error: xml_reader::err::LibraryXmlReader) -> Self {
             Cli::LibraryXmlReader(error)
         }
     }
 
+    impl From<navidrome_writer::err::NavidromeWriter> for Cli {
+        fn from(error: navidrome_writer::err::NavidromeWriter) -> Self {
+            Cli::NavidromeWriter(error)
+        }
+    }
+
     impl From<rusqlite::Error> for Cli {
         fn from(error: rusqlite::Error) -> Self {
-            Cli::NavidromeSql(error)
+            Cli::NavidromeWriter(navidrome_writer::err::NavidromeWriter::Sql(error))
+        }
+    }
+
+    impl From<apple_navidrome_lib::err::Error> for Cli {
+        fn from(error: apple_navidrome_lib::err::Error) -> Self {
+            match error {
+                apple_navidrome_lib::err::Error::Config(e) => Cli::Config(e),
+                apple_navidrome_lib::err::Error::LibraryXmlReader(e) => Cli::LibraryXmlReader(e),
+                apple_navidrome_lib::err::Error::NavidromeWriter(e) => Cli::NavidromeWriter(e),
+            }
+        }
+    }
+}
+
+/// Transfer library information from Apple Music to Navidrome.
+///
+/// Global options override the corresponding values in the config file.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Args {
+    /// The config file to read
+    #[arg(long, global = true, default_value = "an_config.toml")]
+    config: PathBuf,
+
+    /// The log level: off, error, warn, info, debug, or trace
+    #[arg(long, global = true)]
+    log_level: Option<String>,
+
+    /// The Apple Music library XML file
+    #[arg(long, global = true)]
+    library: Option<PathBuf>,
+
+    /// The Navidrome database to read from
+    #[arg(long, global = true)]
+    database: Option<PathBuf>,
+
+    /// Skip values in the library which can't be read, listing them in the run report, rather than stopping
+    #[arg(long, global = true)]
+    lenient: bool,
+
+    /// Go through a sync, reconcile, or tags command without writing to the Navidrome database
+    #[arg(long, global = true)]
+    dry_run: bool,
+
+    /// With no command, every stage enabled in the config file is run
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Write Apple Music metadata to a copy of the Navidrome database
+    Sync,
+    /// Export Apple Music playlists as m3u files
+    ExportPlaylists,
+    /// Export the parsed Apple Music library as JSON
+    ExportJson {
+        /// Where to write the JSON, in place of the path in the config file
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Export the parsed Apple Music library as CSV files
+    ExportCsv {
+        /// The directory to write to, in place of the directory in the config file
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Export the parsed Apple Music library as a SQLite database
+    ExportSqlite {
+        /// Where to write the database, in place of the path in the config file
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Summarise the Apple Music library
+    Report,
+    /// Show the most played artists, albums, genres, and tracks of the Apple Music library
+    Stats {
+        /// How many entries to show in each list
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+        /// Only count tracks released in this year
+        #[arg(long)]
+        year: Option<usize>,
+    },
+    /// Compare genre, grouping, comments, work, and movement with a copy of the Navidrome database, applying them if set in the config file
+    Tags,
+    /// Find tracks which appear more than once in the Apple Music library
+    Duplicates,
+    /// Write a listening report of the Apple Music library as Markdown and HTML
+    ListeningReport {
+        /// Only cover tracks last played in this year
+        #[arg(long)]
+        year: Option<i32>,
+    },
+    /// Write a default config file
+    InitConfig {
+        /// Replace an existing config file
+        #[arg(long)]
+        force: bool,
+    },
+    /// Check the config file, library, and Navidrome database can be used
+    Validate,
+    /// Show what has changed since an older export of the Apple Music library
+    Diff {
+        /// The older library, compared against the library in the config file
+        older: PathBuf,
+        /// Also write the changes as JSON to this path
+        #[arg(long)]
+        json: Option<PathBuf>,
+    },
+    /// Combine plays, ratings, and stars from Apple Music and Navidrome, writing the result to a copy of the Navidrome database
+    Reconcile,
+    /// Export the plays, ratings, stars, and playlists of the Navidrome user for Apple Music
+    ExportNavidrome {
+        /// Where to write the playlists, in place of the directory in the config file
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Also write the whole Navidrome library as an XML file to this path
+        #[arg(long)]
+        library: Option<PathBuf>,
+    },
+}
+
+impl Args {
+    fn apply_overrides(&self, config: &mut Config) {
+        if let Some(level) = &self.log_level {
+            config.log_level = level.clone();
+        }
+        if let Some(library) = &self.library {
+            config.apple_music_library = library.clone();
+        }
+        if let Some(database) = &self.database {
+            config.navidrome_import_database = database.clone();
+        }
+        if self.lenient {
+            config.lenient_parsing = true;
+        }
+        if self.dry_run {
+            config.dry_run = true;
         }
     }
 }
 
-fn main() -> Result<(), err::Cli> {
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    // Everything is passed to the logger, with filtering done by the max level.
     let mut clog = colog::default_builder();
+    clog.filter_level(log::LevelFilter::Trace);
     clog.init();
-    clog.filter_level(log::LevelFilter::Error);
-    let config = Config::from_file();
+    log::set_max_level(log::LevelFilter::Error);
 
-    clog.filter_level(config.get_log_level());
+    match run(args) {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(e) => {
+            e.log();
+            e.exit_code()
+        }
+    }
+}
 
-    let mut library = Library::from_xml(&config.apple_music_library)?;
-    // let library = Library::from_json(std::path::Path::new("Library.json")).unwrap();
-    log::info!("Found {} tracks", library.tracks.keys().count());
-    log::info!("Found {} playlists", library.playlists.len());
-    library.derive_artist_album_playcounts();
+fn run(args: Args) -> Result<(), err::Cli> {
+    if let Some(Command::InitConfig { force }) = args.command {
+        if !force && std::fs::exists(&args.config).unwrap_or(true) {
+            return Err(err::Cli::ConfigExists { path: args.config });
+        }
+        Config::write_default(&args.config)?;
+        println!(
+            "A default config file has been written to {:?}",
+            args.config
+        );
+        return Ok(());
+    }
 
-    if config.update_navidrome {
-        match std::fs::copy(
-            &config.navidrome_import_database,
-            &config.navidrome_export_database,
-        ) {
-            Err(_) => {
-                log::error!("Failed to create a copy of the navidrome database for export");
-                log::error!("Exiting without any further action.");
-                std::process::exit(1)
+    let mut config = match Config::from_file(&args.config) {
+        Ok(config) => config,
+        Err(config::err::Config::Missing { path }) => {
+            log::set_max_level(log::LevelFilter::Info);
+            Config::write_default(&path)?;
+            log::info!("A default config file has been created.");
+            log::info!(
+                "Please check the contents of the file are okay and then run this program again."
+            );
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    };
+    args.apply_overrides(&mut config);
+    log::set_max_level(config.get_log_level());
+
+    let mut run_report = RunReport::default();
+
+    match args.command {
+        None => {
+            let library = read_library(&config, &mut run_report)?;
+
+            if config.update_navidrome {
+                sync(&library, &config, &mut run_report)?;
             }
-            Ok(_) => {
-                log::info!("A copy of the navidrome database has made.");
+
+            if config.apple_music_library_export_json {
+                run_report.time("export json", |_| {
+                    export_json(&library, &config.apple_music_library_json_export_path)
+                });
             }
-        };
 
-        let writer =
-            NavidromeWriter::from(std::path::Path::new(&config.navidrome_export_database))?;
-        let user_id = writer.get_navidrome_user_id(&config);
+            if config.export_apple_music_playlists {
+                run_report.time("export playlists", |r| {
+                    r.playlist_exports = Some(export_playlists(&library, &config))
+                });
+            }
 
-        writer.update_tracks(&library, &user_id, &config);
+            write_run_report(&run_report, &config);
+        }
 
-        match writer.set_artist_album_counts(&library, &user_id) {
-            Ok(_) => {}
-            Err(e) => {
-                log::error!("Error updating artist counts:\n{e:?}");
+        Some(Command::Sync) => {
+            let library = read_library(&config, &mut run_report)?;
+            sync(&library, &config, &mut run_report)?;
+            write_run_report(&run_report, &config);
+        }
+
+        Some(Command::ExportPlaylists) => {
+            let library = read_library(&config, &mut run_report)?;
+            run_report.time("export playlists", |r| {
+                r.playlist_exports = Some(export_playlists(&library, &config))
+            });
+            write_run_report(&run_report, &config);
+        }
+
+        Some(Command::ExportJson { output }) => {
+            let library = read_library(&config, &mut run_report)?;
+            let path = output.unwrap_or(config.apple_music_library_json_export_path.clone());
+            run_report.time("export json", |_| export_json(&library, &path));
+            write_run_report(&run_report, &config);
+        }
+
+        Some(Command::ExportCsv { output }) => {
+            let library = read_library(&config, &mut run_report)?;
+            let directory = output.unwrap_or(config.csv_export_directory.clone());
+            run_report.time("export csv", |_| match library.csv_export(&directory) {
+                Ok(_) => log::info!("Apple music library csv export ok"),
+                Err(e) => log::error!("Error when exporting apple music library to CSV\n{e:?}"),
+            });
+            write_run_report(&run_report, &config);
+        }
+
+        Some(Command::ExportSqlite { output }) => {
+            let library = read_library(&config, &mut run_report)?;
+            let path = output.unwrap_or(config.sqlite_export_path.clone());
+            run_report.time("export sqlite", |_| match library.sqlite_export(&path) {
+                Ok(_) => log::info!("Apple music library sqlite export ok"),
+                Err(e) => log::error!("Error when exporting apple music library to SQLite\n{e:?}"),
+            });
+            write_run_report(&run_report, &config);
+        }
+
+        Some(Command::Report) => {
+            let library = read_library(&config, &mut run_report)?;
+            report(&library);
+        }
+
+        Some(Command::Stats { top, year }) => {
+            let library = read_library(&config, &mut run_report)?;
+            stats(&library, top, year);
+        }
+
+        Some(Command::Tags) => {
+            let library = read_library(&config, &mut run_report)?;
+            let writer = export_database(&config, &mut run_report)?;
+            sync_tags(&library, &config, &writer, &mut run_report)?;
+            write_run_report(&run_report, &config);
+        }
+
+        Some(Command::Duplicates) => {
+            let library = read_library(&config, &mut run_report)?;
+            duplicates(&library, &config)?;
+        }
+
+        Some(Command::ListeningReport { year }) => {
+            let library = read_library(&config, &mut run_report)?;
+            listening_report(&library, &config, year)?;
+        }
+
+        Some(Command::Validate) => validate(&config)?,
+
+        Some(Command::Diff { older, json }) => {
+            let library = read_library(&config, &mut run_report)?;
+            let (older, _) = load_library(&older, &config)?;
+            let diff = older.diff(&library);
+            print!("{}", diff.to_text());
+            if let Some(path) = json {
+                diff.json_export(&path)?;
             }
-        };
+        }
+
+        Some(Command::Reconcile) => {
+            let library = read_library(&config, &mut run_report)?;
+            reconcile(&library, &config, &mut run_report)?;
+            write_run_report(&run_report, &config);
+        }
+
+        Some(Command::ExportNavidrome { output, library }) => {
+            let directory = output.unwrap_or(config.navidrome_playlist_export_directory.clone());
+            export_navidrome(&config, &directory, library.as_deref())?;
+        }
+
+        Some(Command::InitConfig { .. }) => unreachable!(),
     }
 
-    if config.apple_music_library_export_json {
-        match library.json_export(&config.apple_music_library_json_export_path) {
-            Ok(_) => {
-                log::info!("Apple music library json export ok");
-            }
-            Err(e) => {
-                log::error!("Error when exporting apple music library to JSON\n{e:?}")
+    Ok(())
+}
+
+/// Reads the library at `path`, as JSON if previously exported as JSON and otherwise as an Apple Music library.
+fn load_library(
+    path: &Path,
+    config: &Config,
+) -> Result<(Library, Vec<Diagnostic>), LibraryXmlReader> {
+    match path
+        .extension()
+        .is_some_and(|extension| extension == "json")
+    {
+        true => Library::from_json(path)
+            .map(|library| (library, vec![]))
+            .map_err(LibraryXmlReader::from),
+        false => Library::from_xml_with_mode(path, config.parse_mode()),
+    }
+}
+
+fn read_library(config: &Config, run_report: &mut RunReport) -> Result<Library, err::Cli> {
+    let (mut library, diagnostics) = run_report.time("read library", |_| {
+        load_library(&config.apple_music_library, config)
+    })?;
+    if !diagnostics.is_empty() {
+        log::warn!(
+            "{} problems were skipped when reading the library",
+            diagnostics.len()
+        );
+    }
+    run_report.library_diagnostics = diagnostics
+        .iter()
+        .map(|d| match d.position {
+            Some(position) => format!("{position} \"{}\": {:?}", d.key, d.error),
+            None => format!("\"{}\": {:?}", d.key, d.error),
+        })
+        .collect();
+    log::info!("Found {} tracks", library.tracks.keys().count());
+    log::info!("Found {} playlists", library.playlists.len());
+    library.derive_artist_album_playcounts_with(&config.album_inference());
+    run_report.record_library(&library);
+    let warnings = run_report.time("check library", |_| library.validate(false));
+    if !warnings.is_empty() {
+        log::warn!(
+            "{} suspicious values were found in the library, run the validate command for details",
+            warnings.len()
+        );
+    }
+    run_report.library_warnings = count_warnings(&warnings);
+    Ok(library)
+}
+
+fn write_run_report(run_report: &RunReport, config: &Config) {
+    if !config.write_run_report {
+        return;
+    }
+    if let Err(e) = run_report.json_export(&config.info_path(&config.run_report_json_file)) {
+        log::warn!("Error when writing the run report as JSON\n{e:?}");
+    }
+    if let Err(e) = run_report.markdown_export(&config.info_path(&config.run_report_markdown_file))
+    {
+        log::warn!("Error when writing the run report as Markdown\n{e:?}");
+    }
+}
+
+/// The copy of the Navidrome database to write to, or for a dry run a copy in memory, so no file is changed.
+fn export_database(
+    config: &Config,
+    run_report: &mut RunReport,
+) -> Result<NavidromeWriter, err::Cli> {
+    if config.dry_run {
+        run_report.dry_run = true;
+        log::info!(
+            "A dry run, so changes are made to a copy of the navidrome database in memory only"
+        );
+        return Ok(NavidromeWriter::scratch(&config.navidrome_import_database)?);
+    }
+    copy_database(config)?;
+    Ok(NavidromeWriter::from(&config.navidrome_export_database)?)
+}
+
+fn copy_database(config: &Config) -> Result<(), err::Cli> {
+    match std::fs::copy(
+        &config.navidrome_import_database,
+        &config.navidrome_export_database,
+    ) {
+        Err(error) => Err(err::Cli::DatabaseCopy { error }),
+        Ok(_) => {
+            log::info!("A copy of the navidrome database has made.");
+            Ok(())
+        }
+    }
+}
+
+pub fn sync(
+    library: &Library,
+    config: &Config,
+    run_report: &mut RunReport,
+) -> Result<(), err::Cli> {
+    let writer = export_database(config, run_report)?;
+    let user_id = writer.get_navidrome_user_id(config)?;
+
+    let snapshot_path = config.info_path(&config.sync_snapshot_file);
+    let mut snapshot = match config.incremental_sync {
+        true => SyncSnapshot::from_file(&snapshot_path)?,
+        false => SyncSnapshot::default(),
+    };
+    if snapshot.is_later_than(library) {
+        return Err(err::Cli::OlderLibrary {
+            library_date: library.date,
+            snapshot_date: snapshot.library_date,
+        });
+    }
+
+    let track_sync = run_report.time("update tracks", |_| {
+        writer.update_tracks(library, &user_id, config, &mut snapshot)
+    })?;
+    run_report.track_sync = Some(track_sync);
+
+    let mut complete = true;
+    match run_report.time("update artists and albums", |_| {
+        writer.set_artist_album_counts(library, &user_id, &config.artist_credits())
+    }) {
+        Ok(count_sync) => run_report.count_sync = Some(count_sync),
+        Err(e) => {
+            log::error!("Error updating artist counts:\n{e:?}");
+            complete = false;
+        }
+    };
+
+    if config.sync_date_added {
+        let (date_sync, changes) =
+            run_report.time("update dates added", |_| writer.update_dates_added(library))?;
+        run_report.date_sync = Some(date_sync);
+        if !config.dry_run {
+            if let Err(e) = write_date_changes(&changes, config) {
+                log::warn!("Error when recording the dates replaced\n{e:?}");
             }
         }
     }
 
-    if config.export_apple_music_playlists {
-        export_playlists(&library, &config);
+    if config.compare_tags {
+        sync_tags(library, config, &writer, run_report)?;
+    }
+
+    // recorded last, so a sync which stops part way is not taken to have been made
+    if complete && !config.dry_run {
+        if let Err(e) = snapshot.json_export(&snapshot_path) {
+            log::warn!("Error when recording the state of the sync\n{e:?}");
+        }
+    }
+    Ok(())
+}
+
+fn sync_tags(
+    library: &Library,
+    config: &Config,
+    writer: &NavidromeWriter,
+    run_report: &mut RunReport,
+) -> Result<(), err::Cli> {
+    let (tag_sync, differences) = run_report.time("compare tags", |_| {
+        writer.tag_differences(library, config.apply_tags)
+    })?;
+    // written on a dry run too, to show what would change
+    if !differences.is_empty() {
+        match write_tag_differences(&differences, config) {
+            Ok(_) => log::info!(
+                "{} tags differ between Apple Music and Navidrome, a file listing these and a script to set them has been made.",
+                differences.len()
+            ),
+            Err(e) => log::warn!("Error when writing the tag differences\n{e:?}"),
+        }
+    }
+    run_report.tag_sync = Some(tag_sync);
+    Ok(())
+}
+
+pub fn reconcile(
+    library: &Library,
+    config: &Config,
+    run_report: &mut RunReport,
+) -> Result<(), err::Cli> {
+    let writer = export_database(config, run_report)?;
+    let user_id = writer.get_navidrome_user_id(config)?;
+
+    let (reconciliation, changes) = run_report.time("reconcile tracks", |_| {
+        writer.reconcile_tracks(library, &user_id, config)
+    })?;
+    run_report.reconciliation = Some(reconciliation);
+
+    if !changes.is_empty() {
+        match write_apple_changes(&changes, config) {
+            Ok(_) => log::info!(
+                "{} changes are needed for Apple Music to agree, a file listing these has been made.",
+                changes.len()
+            ),
+            Err(e) => log::warn!("Error when writing the changes needed for Apple Music\n{e:?}"),
+        }
+    }
+
+    Ok(())
+}
+
+pub fn export_json(library: &Library, path: &Path) {
+    match library.json_export(path) {
+        Ok(_) => {
+            log::info!("Apple music library json export ok");
+        }
+        Err(e) => {
+            log::error!("Error when exporting apple music library to JSON\n{e:?}")
+        }
+    }
+}
+
+/// Exports each playlist of the Navidrome user to `directory`, and the whole library to `library_path` if given.
+pub fn export_navidrome(
+    config: &Config,
+    directory: &Path,
+    library_path: Option<&Path>,
+) -> Result<(), err::Cli> {
+    let user_id =
+        NavidromeWriter::from(&config.navidrome_import_database)?.get_navidrome_user_id(config)?;
+    let library = Library::from_navidrome(&config.navidrome_import_database, &user_id, config)?;
+    log::info!("Found {} tracks", library.tracks.len());
+    log::info!("Found {} playlists", library.playlists.len());
+
+    if let Some(path) = library_path {
+        match library.xml_export(path) {
+            Ok(_) => log::info!("Navidrome library export ok"),
+            Err(e) => log::error!("Error when exporting the Navidrome library\n{e:?}"),
+        }
+    }
+
+    std::fs::create_dir_all(directory)?;
+    for playlist in &library.playlists {
+        let path = directory.join(playlist.file_name("xml"));
+        match library.playlist_xml_export(playlist, &path) {
+            Ok(_) => log::info!("Exported playlist: {}", playlist.name),
+            Err(e) => log::error!("Failed to export playlist: {}\n{e:?}", playlist.name),
+        }
+    }
+    Ok(())
+}
+
+pub fn report(library: &Library) {
+    let album_count: usize = library.counts.values().map(|a| a.albums.len()).sum();
+    let play_count: usize = library.tracks.values().map(|t| t.play_count).sum();
+    let loved_count = library
+        .tracks
+        .values()
+        .filter(|t| t.loved || t.favourited)
+        .count();
+
+    println!("Library date: {}", library.date);
+    if let Some(version) = &library.application_version {
+        println!("Application version: {version}");
+    }
+    if let Some(folder) = library.music_folder_path() {
+        println!("Music folder: {}", folder.display());
     }
+    println!("Tracks: {}", library.tracks.len());
+    println!("Playlists: {}", library.playlists.len());
+    println!("Artists: {}", library.counts.len());
+    println!("Albums: {album_count}");
+    println!("Plays: {play_count}");
+    println!("Loved or favourited: {loved_count}");
+}
 
+fn hours(duration: Duration) -> String {
+    format!("{:.1} hours", duration.as_secs_f64() / 3600.0)
+}
+
+pub fn stats(library: &Library, top: usize, year: Option<usize>) {
+    let query = || match year {
+        Some(year) => library.query().year(year),
+        None => library.query(),
+    };
+
+    let totals = query().stats();
+    println!("Tracks: {}", totals.tracks);
+    println!("Plays: {}", totals.plays);
+    println!("Listening time: {}", hours(totals.listening_time));
+    println!("Loved or favourited: {}", totals.loved);
+    println!("Never played: {}", totals.never_played);
+
+    for (title, grouping, measure) in [
+        (
+            "Top artists by plays",
+            Grouping::AlbumArtist,
+            Measure::Plays,
+        ),
+        ("Top albums by plays", Grouping::Album, Measure::Plays),
+        (
+            "Top genres by listening time",
+            Grouping::Genre,
+            Measure::ListeningTime,
+        ),
+        (
+            "Top decades by listening time",
+            Grouping::Decade,
+            Measure::ListeningTime,
+        ),
+    ] {
+        println!("\n{title}:");
+        for group in query().top_groups(grouping, measure, top) {
+            let value = match measure {
+                Measure::ListeningTime => hours(group.stats.listening_time),
+                _ => format!("{} plays", group.stats.plays),
+            };
+            println!("  {} ({value})", group.key);
+        }
+    }
+
+    let describe = |track: &Track| {
+        format!(
+            "{} - {}",
+            track.artist.as_deref().unwrap_or("[No artist]"),
+            track.title.as_deref().unwrap_or("[No title]")
+        )
+    };
+    println!("\nTop tracks by plays:");
+    for track in query().played().top(Sort::Plays, top) {
+        println!("  {} ({} plays)", describe(track), track.play_count);
+    }
+
+    let neglected = query().loved().unplayed();
+    println!("\nLoved but never played: {}", neglected.count());
+    for track in neglected.top(Sort::DateAdded, top) {
+        println!("  {}", describe(track));
+    }
+
+    println!("\nAdded per year:");
+    for (year, count) in query().added_per_year() {
+        println!("  {year}: {count}");
+    }
+}
+
+pub fn duplicates(library: &Library, config: &Config) -> Result<(), err::Cli> {
+    let clusters = library.duplicates(config.duplicate_tolerance());
+    let describe = |id: &String| match library.tracks.get(id) {
+        Some(track) => format!(
+            "{id}: {}, {} plays, {} kbps, {} bytes",
+            track.kind.as_deref().unwrap_or("[No kind]"),
+            track.play_count,
+            track.bit_rate.unwrap_or_default(),
+            track.size
+        ),
+        None => id.clone(),
+    };
+    for cluster in &clusters {
+        println!(
+            "{} - {} - {} ({:?})",
+            cluster.artist.as_deref().unwrap_or("[No artist]"),
+            cluster.album.as_deref().unwrap_or("[No album]"),
+            cluster.title.as_deref().unwrap_or("[No title]"),
+            cluster.reason
+        );
+        println!("  keep {}", describe(&cluster.canonical));
+        for id in &cluster.duplicates {
+            println!("       {}", describe(id));
+        }
+    }
+    println!("{} duplicate clusters", clusters.len());
+
+    let path = config.info_path(&config.duplicates_file);
+    std::fs::write(&path, serde_json::to_string_pretty(&clusters)?)?;
+    log::info!("Duplicates written to {}", path.display());
     Ok(())
 }
 
-pub fn export_playlists(library: &Library, config: &Config) {
+pub fn listening_report(
+    library: &Library,
+    config: &Config,
+    year: Option<i32>,
+) -> Result<(), err::Cli> {
+    let report = ListeningReport::from_library(library, year, config.listening_report_top);
+    let markdown = config.info_path(&config.listening_report_markdown_file);
+    report.markdown_export(&markdown)?;
+    let html = config.info_path(&config.listening_report_html_file);
+    report.html_export(&html)?;
+    log::info!(
+        "Listening report written to {} and {}",
+        markdown.display(),
+        html.display()
+    );
+    Ok(())
+}
+
+pub fn validate(config: &Config) -> Result<(), err::Cli> {
+    let mut ok = true;
+
+    match load_library(&config.apple_music_library, config) {
+        Ok((library, diagnostics)) => {
+            println!(
+                "Library {:?} read with {} tracks and {} playlists",
+                config.apple_music_library,
+                library.tracks.len(),
+                library.playlists.len()
+            );
+            for diagnostic in diagnostics {
+                match diagnostic.position {
+                    Some(position) => println!(
+                        "Skipped \"{}\" at {position}: {:?}",
+                        diagnostic.key, diagnostic.error
+                    ),
+                    None => println!("Skipped \"{}\": {:?}", diagnostic.key, diagnostic.error),
+                }
+            }
+
+            let warnings = library.validate(config.check_missing_files);
+            for warning in &warnings {
+                println!("{:?}: {}", warning.check, warning.message);
+            }
+            for (check, count) in count_warnings(&warnings) {
+                println!("{check:?}: {count} warnings");
+            }
+            let path = config.info_path(&config.library_warnings_file);
+            match serde_json::to_string_pretty(&warnings) {
+                Ok(json) => {
+                    if let Err(e) = std::fs::write(&path, json) {
+                        log::warn!("Error when writing the library warnings\n{e:?}");
+                    }
+                }
+                Err(e) => log::warn!("Error when writing the library warnings\n{e:?}"),
+            }
+        }
+        Err(e) => {
+            ok = false;
+            println!(
+                "Library {:?} could not be read: {e:?}",
+                config.apple_music_library
+            );
+        }
+    }
+
+    if !std::fs::exists(&config.navidrome_import_database).unwrap_or(false) {
+        ok = false;
+        println!(
+            "Navidrome database {:?} does not exist",
+            config.navidrome_import_database
+        );
+    } else {
+        match NavidromeWriter::from(&config.navidrome_import_database) {
+            Ok(writer) => match &config.navidrome_user_id {
+                Some(id) => println!("Navidrome user id {id} taken from the config"),
+                None => match writer.user_ids(&config.navidrome_user) {
+                    Ok(ids) if ids.len() == 1 => {
+                        println!("Navidrome user \"{}\" found", config.navidrome_user)
+                    }
+                    Ok(ids) => {
+                        ok = false;
+                        println!(
+                            "Navidrome user \"{}\" has {} ids, expected one",
+                            config.navidrome_user,
+                            ids.len()
+                        );
+                    }
+                    Err(e) => {
+                        ok = false;
+                        println!("Navidrome users could not be read: {e:?}");
+                    }
+                },
+            },
+            Err(e) => {
+                ok = false;
+                println!(
+                    "Navidrome database {:?} could not be opened: {e:?}",
+                    config.navidrome_import_database
+                );
+            }
+        }
+    }
+
+    match ok {
+        true => Ok(()),
+        false => Err(err::Cli::Invalid),
+    }
+}
+
+pub fn export_playlists(library: &Library, config: &Config) -> Vec<PlaylistExport> {
+    let mut exports = vec![];
+
     if !std::fs::exists(&config.apple_music_playlist_export_directory).unwrap_or(true) {
         match std::fs::create_dir(&config.apple_music_playlist_export_directory) {
             Ok(_) => {}
             Err(e) => {
                 log::error!("Could not create directory for playlists.");
                 log::error!("{e:?}");
-                return;
+                return exports;
             }
         }
     }
@@ -120,22 +973,35 @@ pub fn export_playlists(library: &Library, config: &Config) {
     for playlist in &library.playlists {
         if config
             .apple_music_ignored_playlists
-            .iter()
-            .any(|l| *l == playlist.name)
+            .contains(&playlist.name)
             || playlist.folder
         {
             continue;
         }
         log::trace!("Creating playlist: {}", playlist.name);
+        let mut export = PlaylistExport {
+            name: playlist.name.clone(),
+            tracks: playlist.track_ids.len(),
+            missing_tracks: playlist
+                .track_ids
+                .iter()
+                .filter(|id| !library.tracks.contains_key(*id))
+                .cloned()
+                .collect(),
+            exported: false,
+        };
         match playlist.export_m3u(
             &config.apple_music_playlist_export_directory,
             &library.tracks,
         ) {
-            Ok(_) => {}
+            Ok(_) => export.exported = true,
             Err(e) => {
                 log::warn!("Error when creating playlist {}:", playlist.name);
                 log::warn!("{e:?}");
             }
         };
+        exports.push(export);
     }
+
+    exports
 }
@@ -1,27 +1,119 @@
-use std::{path::PathBuf, str::FromStr};
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
 
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    navidrome_writer::reconcile::Rule,
+    structs::{
+        credits::ArtistCredits,
+        inference::{AlbumInference, AlbumStrategy},
+    },
+    xml_reader::ParseMode,
+};
+
+pub mod err {
+    use std::path::PathBuf;
+
+    #[derive(Debug)]
+    pub enum Config {
+        Missing {
+            path: PathBuf,
+        },
+        Io {
+            path: PathBuf,
+            error: std::io::Error,
+        },
+        Create {
+            path: PathBuf,
+            error: std::io::Error,
+        },
+        Toml(toml::de::Error),
+        TomlSerialize(toml::ser::Error),
+    }
+
+    impl From<toml::de::Error> for Config {
+        fn from(error: toml::de::Error) -> Self {
+            Config::Toml(error)
+        }
+    }
+
+    impl From<toml::ser::Error> for Config {
+        fn from(error: toml::ser::Error) -> Self {
+            Config::TomlSerialize(error)
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
     pub apple_music_library: PathBuf,
+    pub lenient_parsing: bool,
     pub apple_music_library_export_json: bool,
     pub apple_music_library_json_export_path: PathBuf,
+    pub csv_export_directory: PathBuf,
+    pub sqlite_export_path: PathBuf,
 
     pub export_apple_music_playlists: bool,
     pub apple_music_playlist_export_directory: PathBuf,
     pub apple_music_ignored_playlists: Vec<String>,
 
+    pub album_playcount_strategy: AlbumStrategy,
+    pub album_playcount_min_seconds: u64,
+    pub album_playcount_percentile: f64,
+    pub artist_credit_allow_list: Vec<String>,
+
     pub update_navidrome: bool,
     pub navidrome_import_database: PathBuf,
     pub navidrome_export_database: PathBuf,
     pub navidrome_user: String,
     pub navidrome_user_id: Option<String>,
+    pub incremental_sync: bool,
+    pub sync_snapshot_file: PathBuf,
+    pub merge_duplicates: bool,
+    pub sync_date_added: bool,
+    pub date_added_changes_file: PathBuf,
+    pub dry_run: bool,
+    pub compare_tags: bool,
+    pub apply_tags: bool,
+    pub tag_differences_file: PathBuf,
+    pub tag_script_file: PathBuf,
+    pub tag_script_navidrome_folder: String,
+    pub tag_script_music_folder: String,
+    pub export_skips: bool,
+    pub skip_rating_ratio: Option<f64>,
+    pub skip_rating_min_skips: usize,
+    pub skip_rating_stars: usize,
+    pub duplicate_duration_tolerance_seconds: u64,
+    pub duplicates_file: PathBuf,
+    pub navidrome_playlist_export_directory: PathBuf,
+    pub export_navidrome_folder: String,
+    pub export_music_folder: String,
+    pub reconcile_play_count: Rule,
+    pub reconcile_rating: Rule,
+    pub reconcile_starred: Rule,
+    pub apple_changes_file: PathBuf,
+    pub apple_changes_csv_file: PathBuf,
 
     pub record_failed_matches: bool,
     pub info_folder: PathBuf,
     pub no_match_file: PathBuf,
+    pub no_match_csv_file: PathBuf,
+    pub nearest_candidates: usize,
     pub multiple_matches_file: PathBuf,
+    pub check_missing_files: bool,
+    pub library_warnings_file: PathBuf,
+
+    pub write_run_report: bool,
+    pub run_report_json_file: PathBuf,
+    pub run_report_markdown_file: PathBuf,
+    pub listening_report_markdown_file: PathBuf,
+    pub listening_report_html_file: PathBuf,
+    pub listening_report_top: usize,
 
     pub log_level: String,
 }
@@ -30,8 +122,11 @@ impl Default for Config {
     fn default() -> Self {
         Config {
             apple_music_library: PathBuf::from_str("Library.xml").unwrap(),
+            lenient_parsing: false,
             apple_music_library_export_json: true,
             apple_music_library_json_export_path: PathBuf::from_str("Library.json").unwrap(),
+            csv_export_directory: PathBuf::from_str("csv").unwrap(),
+            sqlite_export_path: PathBuf::from_str("Library.sqlite").unwrap(),
 
             export_apple_music_playlists: true,
             apple_music_playlist_export_directory: PathBuf::from_str("playlists").unwrap(),
@@ -41,16 +136,58 @@ impl Default for Config {
                 "Music".to_owned(),
             ]),
 
+            album_playcount_strategy: AlbumStrategy::Minimum,
+            album_playcount_min_seconds: 60,
+            album_playcount_percentile: 25.0,
+            artist_credit_allow_list: vec![],
+
             update_navidrome: true,
             navidrome_import_database: PathBuf::from_str("navidrome.db").unwrap(),
             navidrome_export_database: PathBuf::from_str("navidrome_updated.db").unwrap(),
             navidrome_user: "user".to_string(),
             navidrome_user_id: None,
+            incremental_sync: false,
+            sync_snapshot_file: PathBuf::from_str("sync_snapshot.json").unwrap(),
+            merge_duplicates: false,
+            sync_date_added: false,
+            date_added_changes_file: PathBuf::from_str("date_added_changes.csv").unwrap(),
+            dry_run: false,
+            compare_tags: false,
+            apply_tags: false,
+            tag_differences_file: PathBuf::from_str("tag_differences.csv").unwrap(),
+            tag_script_file: PathBuf::from_str("tag_edits.sh").unwrap(),
+            tag_script_navidrome_folder: String::new(),
+            tag_script_music_folder: String::new(),
+            export_skips: false,
+            skip_rating_ratio: None,
+            skip_rating_min_skips: 5,
+            skip_rating_stars: 1,
+            duplicate_duration_tolerance_seconds: 2,
+            duplicates_file: PathBuf::from_str("duplicates.json").unwrap(),
+            navidrome_playlist_export_directory: PathBuf::from_str("navidrome_playlists").unwrap(),
+            export_navidrome_folder: String::new(),
+            export_music_folder: String::new(),
+            reconcile_play_count: Rule::Max,
+            reconcile_rating: Rule::LatestWins,
+            reconcile_starred: Rule::LatestWins,
+            apple_changes_file: PathBuf::from_str("apple_music_changes.json").unwrap(),
+            apple_changes_csv_file: PathBuf::from_str("apple_music_changes.csv").unwrap(),
 
             record_failed_matches: true,
             info_folder: PathBuf::from_str("info").unwrap(),
             no_match_file: PathBuf::from_str("no_matches.json").unwrap(),
+            no_match_csv_file: PathBuf::from_str("no_matches.csv").unwrap(),
+            nearest_candidates: 3,
             multiple_matches_file: PathBuf::from_str("multiple_matches.json").unwrap(),
+            check_missing_files: false,
+            library_warnings_file: PathBuf::from_str("library_warnings.json").unwrap(),
+
+            write_run_report: true,
+            run_report_json_file: PathBuf::from_str("report.json").unwrap(),
+            run_report_markdown_file: PathBuf::from_str("report.md").unwrap(),
+            listening_report_markdown_file: PathBuf::from_str("listening_report.md").unwrap(),
+            listening_report_html_file: PathBuf::from_str("listening_report.html").unwrap(),
+            listening_report_top: 10,
 
             log_level: "info".to_string(),
         }
@@ -58,50 +195,40 @@ impl Default for Config {
 }
 
 impl Config {
-    pub fn from_file() -> Config {
-        let file_string = match std::fs::read_to_string("an_config.toml") {
+    /// Reads the config at `path`, with a `Missing` error if there is no file to read.
+    pub fn from_file(path: &Path) -> Result<Config, err::Config> {
+        let file_string = match std::fs::read_to_string(path) {
             Ok(contents) => contents,
-            Err(_) => {
-                if !std::fs::exists("an_config.toml").unwrap_or(true) {
-                    let mut file = match std::fs::File::create("an_config.toml") {
-                        Ok(ok) => ok,
-                        Err(e) => {
-                            log::error!("Could create a default config file.");
-                            log::error!("Error: {e:?}");
-                            std::process::exit(1);
-                        }
-                    };
-                    let default_config = Config::default();
-                    let config_toml = toml::to_string_pretty(&default_config).unwrap();
-
-                    match std::io::Write::write_all(&mut file, config_toml.as_bytes()) {
-                        Ok(_) => {
-                            log::info!("A default config file has been created.");
-                            log::info!("Please check the contents of the file are okay and then run this program again.");
-                            std::process::exit(0);
-                        }
-                        Err(e) => {
-                            log::error!("Could write to the default config file after creating.");
-                            log::error!("Error: {e:?}");
-                            std::process::exit(1);
-                        }
-                    };
+            Err(error) => {
+                if !std::fs::exists(path).unwrap_or(true) {
+                    return Err(err::Config::Missing {
+                        path: path.to_path_buf(),
+                    });
                 } else {
-                    log::warn!("There's some issue reading the config file, perhaps delete it and try again?");
-                    std::process::exit(1);
+                    return Err(err::Config::Io {
+                        path: path.to_path_buf(),
+                        error,
+                    });
                 }
             }
         };
-        let config: Config = match toml::from_str(&file_string) {
-            Ok(toml) => toml,
-            Err(e) => {
-                log::error!("There was a problem reading the configuration file.");
-                log::error!("Try fixing the file, or deleting it and running this program again.");
-                log::error!("Error details: {e:?}");
-                std::process::exit(1);
-            }
-        };
-        config
+        let config: Config = toml::from_str(&file_string)?;
+        Ok(config)
+    }
+
+    /// Writes the default config to `path`, replacing any existing file.
+    pub fn write_default(path: &Path) -> Result<(), err::Config> {
+        let default_config = Config::default();
+        let config_toml = toml::to_string_pretty(&default_config)?;
+        let write = std::fs::File::create(path)
+            .and_then(|mut file| std::io::Write::write_all(&mut file, config_toml.as_bytes()));
+        match write {
+            Ok(_) => Ok(()),
+            Err(error) => Err(err::Config::Create {
+                path: path.to_path_buf(),
+                error,
+            }),
+        }
     }
 
     pub fn info_path(&self, path: &PathBuf) -> PathBuf {
@@ -115,6 +242,29 @@ impl Config {
         self.info_folder.join(path)
     }
 
+    pub fn album_inference(&self) -> AlbumInference {
+        AlbumInference {
+            strategy: self.album_playcount_strategy,
+            min_seconds: self.album_playcount_min_seconds,
+            percentile: self.album_playcount_percentile,
+        }
+    }
+
+    pub fn artist_credits(&self) -> ArtistCredits {
+        ArtistCredits::new(&self.artist_credit_allow_list)
+    }
+
+    pub fn duplicate_tolerance(&self) -> Duration {
+        Duration::from_secs(self.duplicate_duration_tolerance_seconds)
+    }
+
+    pub fn parse_mode(&self) -> ParseMode {
+        match self.lenient_parsing {
+            true => ParseMode::Lenient,
+            false => ParseMode::Strict,
+        }
+    }
+
     pub fn get_log_level(&self) -> log::LevelFilter {
         match log::LevelFilter::from_str(&self.log_level) {
             Err(e) => {
@@ -0,0 +1,33 @@
+use crate::{config, navidrome_writer, xml_reader};
+
+/// Errors from any part of the library, for callers which would rather not handle each separately.
+#[derive(Debug)]
+pub enum Error {
+    Config(config::err::Config),
+    LibraryXmlReader(xml_reader::err::LibraryXmlReader),
+    NavidromeWriter(navidrome_writer::err::NavidromeWriter),
+}
+
+impl From<config::err::Config> for Error {
+    fn from(error: config::err::Config) -> Self {
+        Error::Config(error)
+    }
+}
+
+impl From<xml_reader::err::LibraryXmlReader> for Error {
+    fn from(error: xml_reader::err::LibraryXmlReader) -> Self {
+        Error::LibraryXmlReader(error)
+    }
+}
+
+impl From<navidrome_writer::err::NavidromeWriter> for Error {
+    fn from(error: navidrome_writer::err::NavidromeWriter) -> Self {
+        Error::NavidromeWriter(error)
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(error: rusqlite::Error) -> Self {
+        Error::NavidromeWriter(navidrome_writer::err::NavidromeWriter::Sql(error))
+    }
+}
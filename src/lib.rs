@@ -1,4 +1,11 @@
 pub mod structs;
 pub mod xml_reader;
+pub mod xml_writer;
+pub mod navidrome_reader;
 pub mod navidrome_writer;
 pub mod config;
+pub mod err;
+pub mod report;
+
+#[cfg(test)]
+mod test_fixtures;
@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use rusqlite::Connection;
+
+use crate::config::Config;
+use crate::structs::{playlist::Playlist, track::Track, Library, TrackID};
+
+/// Reads the tracks, annotations, and playlists of a Navidrome user.
+pub struct NavidromeReader {
+    pub db: Connection,
+}
+
+/// The path with the start `navidrome_folder` replaced by `music_folder`, or as is if it starts otherwise.
+pub fn replace_folder(path: &str, navidrome_folder: &str, music_folder: &str) -> String {
+    match path.strip_prefix(navidrome_folder) {
+        Some(rest) => format!("{music_folder}{rest}"),
+        None => path.to_owned(),
+    }
+}
+
+/// Encodes a Navidrome path as a location in the form used by Apple Music.
+pub fn encode_location(path: &str) -> String {
+    let encoded = path
+        .split('/')
+        .map(|segment| urlencoding::encode(segment).into_owned())
+        .collect::<Vec<_>>()
+        .join("/");
+    match path.starts_with('/') {
+        true => format!("file://{encoded}"),
+        false => format!("file:///{encoded}"),
+    }
+}
+
+/// Navidrome rates from 0 to 5 and Apple Music from 0 to 100.
+///
+/// Larger values are taken to have been written from Apple Music as is.
+pub fn apple_rating(rating: usize) -> usize {
+    match rating {
+        0..=5 => rating * 20,
+        _ => rating,
+    }
+}
+
+/// The Navidrome rating of an Apple Music rating, to the nearest star.
+pub fn navidrome_rating(rating: usize) -> usize {
+    ((rating + 10) / 20).min(5)
+}
+
+impl NavidromeReader {
+    pub fn from(path: &Path) -> Result<Self, rusqlite::Error> {
+        let connection = Connection::open(path)?;
+        Ok(NavidromeReader { db: connection })
+    }
+
+    /// Every row of the media_file table as a track, without annotations.
+    ///
+    /// Locations are the Navidrome paths with `export_navidrome_folder` replaced by `export_music_folder`.
+    pub fn tracks(&self, config: &Config) -> Result<HashMap<TrackID, Track>, rusqlite::Error> {
+        let query_string = "
+SELECT id, path, title, album, artist, album_artist, track_number, disc_number, year,
+duration, size, bit_rate, genre, comment, created_at, compilation
+FROM media_file
+";
+        let mut tracks = HashMap::default();
+        let mut stmt = self.db.prepare(query_string)?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let id: String = row.get("id")?;
+            let path: Option<String> = row.get("path")?;
+            let track = Track {
+                id: id.clone(),
+                persistent_id: id.clone(),
+                location: path
+                    .map(|path| {
+                        encode_location(&replace_folder(
+                            &path,
+                            &config.export_navidrome_folder,
+                            &config.export_music_folder,
+                        ))
+                    })
+                    .unwrap_or_default(),
+                title: row.get("title")?,
+                album_title: row.get("album")?,
+                artist: row.get("artist")?,
+                album_artist: row.get("album_artist")?,
+                track_number: row.get("track_number")?,
+                disc_number: row.get("disc_number")?,
+                year: row.get("year")?,
+                duration: Duration::from_secs_f64(
+                    row.get::<_, Option<f64>>("duration")?.unwrap_or(0.0),
+                ),
+                size: row.get::<_, Option<usize>>("size")?.unwrap_or(0),
+                bit_rate: row.get("bit_rate")?,
+                genre: row.get("genre")?,
+                comments: row
+                    .get::<_, Option<String>>("comment")?
+                    .filter(|c| !c.is_empty()),
+                // a date in a format chrono does not recognise is treated as missing
+                date_added: row
+                    .get::<_, Option<DateTime<Utc>>>("created_at")
+                    .unwrap_or(None)
+                    .unwrap_or_default(),
+                compilation: row.get::<_, Option<bool>>("compilation")?.unwrap_or(false),
+                ..Default::default()
+            };
+            tracks.insert(id, track);
+        }
+        Ok(tracks)
+    }
+
+    /// Sets the play count, play date, rating, and loved fields of `tracks` from the annotations of the user.
+    pub fn annotate(
+        &self,
+        tracks: &mut HashMap<TrackID, Track>,
+        user_id: &str,
+    ) -> Result<(), rusqlite::Error> {
+        let query_string = "
+SELECT item_id, play_count, play_date, rating, starred
+FROM annotation
+WHERE user_id = :user_id AND item_type = 'media_file'
+";
+        let mut stmt = self.db.prepare(query_string)?;
+        let mut rows = stmt.query(&[(":user_id", user_id)])?;
+        while let Some(row) = rows.next()? {
+            let item_id: String = row.get("item_id")?;
+            let Some(track) = tracks.get_mut(&item_id) else {
+                log::trace!("An annotation for a missing media file: {item_id}");
+                continue;
+            };
+            track.play_count = row.get::<_, Option<usize>>("play_count")?.unwrap_or(0);
+            track.play_date = row.get("play_date").unwrap_or(None);
+            track.rating = apple_rating(row.get::<_, Option<usize>>("rating")?.unwrap_or(0));
+            track.loved = row.get::<_, Option<bool>>("starred")?.unwrap_or(false);
+        }
+        Ok(())
+    }
+
+    /// The playlists owned by the user, with tracks in playlist order.
+    pub fn playlists(&self, user_id: &str) -> Result<Vec<Playlist>, rusqlite::Error> {
+        let mut playlists = vec![];
+        let mut stmt = self
+            .db
+            .prepare("SELECT id, name, comment FROM playlist WHERE owner_id = :user_id")?;
+        let mut rows = stmt.query(&[(":user_id", user_id)])?;
+        while let Some(row) = rows.next()? {
+            let id: String = row.get("id")?;
+            playlists.push(Playlist {
+                name: row.get::<_, Option<String>>("name")?.unwrap_or_default(),
+                description: row.get::<_, Option<String>>("comment")?.unwrap_or_default(),
+                track_ids: self.playlist_track_ids(&id)?,
+                persistent_id: id,
+                ..Default::default()
+            });
+        }
+        Ok(playlists)
+    }
+
+    fn playlist_track_ids(&self, playlist_id: &str) -> Result<Vec<TrackID>, rusqlite::Error> {
+        let mut stmt = self.db.prepare(
+            "SELECT media_file_id FROM playlist_tracks WHERE playlist_id = :playlist_id ORDER BY id",
+        )?;
+        let rows = stmt.query_map(&[(":playlist_id", playlist_id)], |row| row.get(0))?;
+        rows.collect()
+    }
+
+    /// The library of the user, with tracks keyed by media file id.
+    pub fn library(&self, user_id: &str, config: &Config) -> Result<Library, rusqlite::Error> {
+        let mut tracks = self.tracks(config)?;
+        self.annotate(&mut tracks, user_id)?;
+        Ok(Library {
+            date: Utc::now(),
+            tracks,
+            playlists: self.playlists(user_id)?,
+            ..Default::default()
+        })
+    }
+}
+
+impl Library {
+    /// Reads the library of a Navidrome user, as a counterpart to [`Library::from_xml`].
+    pub fn from_navidrome(
+        path: &Path,
+        user_id: &str,
+        config: &Config,
+    ) -> Result<Library, rusqlite::Error> {
+        NavidromeReader::from(path)?.library(user_id, config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locations_in_the_music_folder() {
+        let location = |path: &str, navidrome_folder: &str, music_folder: &str| {
+            encode_location(&replace_folder(path, navidrome_folder, music_folder))
+        };
+        assert_eq!(
+            location("/music/Band/1 One.mp3", "", ""),
+            "file:///music/Band/1%20One.mp3"
+        );
+        assert_eq!(
+            location("/music/Band/1.mp3", "/music/", "/Users/me/Music/"),
+            "file:///Users/me/Music/Band/1.mp3"
+        );
+        assert_eq!(
+            location("Band/1.mp3", "", "/Users/me/Music/"),
+            "file:///Users/me/Music/Band/1.mp3"
+        );
+        assert_eq!(
+            location("/other/Band/1.mp3", "/music/", "/Users/me/Music/"),
+            "file:///other/Band/1.mp3"
+        );
+    }
+}
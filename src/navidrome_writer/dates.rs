@@ -0,0 +1,120 @@
+use std::collections::BTreeSet;
+
+use chrono::{DateTime, Utc};
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Config, report::DateSync, structs::Library};
+
+use super::{err, NavidromeWriter, TrackMatcher};
+
+/// A change to the `created_at` of a media file or album, with the value before so it can be undone.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DateChange {
+    pub table: String,
+    pub id: String,
+    pub before: Option<String>,
+    pub after: String,
+}
+
+impl NavidromeWriter {
+    fn created_at(&self, table: &str, id: &str) -> Result<Option<String>, rusqlite::Error> {
+        self.db
+            .query_row(
+                &format!("SELECT created_at FROM {table} WHERE id = :id"),
+                &[(":id", id)],
+                |row| row.get::<_, Option<String>>(0),
+            )
+            .optional()
+            .map(Option::flatten)
+    }
+
+    fn set_created_at(
+        &self,
+        table: &str,
+        id: &str,
+        after: &str,
+        changes: &mut Vec<DateChange>,
+    ) -> Result<(), rusqlite::Error> {
+        let before = self.created_at(table, id)?;
+        if before.as_deref() == Some(after) {
+            return Ok(());
+        }
+        self.db.execute(
+            &format!("UPDATE {table} SET created_at = :after WHERE id = :id"),
+            &[(":after", after), (":id", id)],
+        )?;
+        changes.push(DateChange {
+            table: table.to_owned(),
+            id: id.to_owned(),
+            before,
+            after: after.to_owned(),
+        });
+        Ok(())
+    }
+
+    /// Sets the `created_at` of each matched media file to the date the track was added to Apple Music,
+    /// and of each album with a changed media file to the earliest of its media files.
+    pub fn update_dates_added(
+        &self,
+        library: &Library,
+    ) -> Result<(DateSync, Vec<DateChange>), err::NavidromeWriter> {
+        let mut sync = DateSync::default();
+        let mut changes = vec![];
+        let mut tracks: Vec<_> = library.tracks.values().collect();
+        tracks.sort_by(|a, b| a.id.cmp(&b.id));
+        for track in tracks {
+            let mut matcher = TrackMatcher::from_track(track);
+            if self.item_ids(&mut matcher)?.len() != 1 {
+                sync.unmatched += 1;
+                continue;
+            }
+            let item_id = matcher.item_id.as_deref().unwrap_or_default();
+            // written as rusqlite writes other dates, so values compare as text
+            let after = sql_date(track.date_added);
+            self.set_created_at("media_file", item_id, &after, &mut changes)?;
+        }
+        sync.media_files = changes.len();
+
+        let album_ids: BTreeSet<String> = changes
+            .iter()
+            .filter_map(|change| {
+                self.db
+                    .query_row(
+                        "SELECT album_id FROM media_file WHERE id = :id",
+                        &[(":id", &change.id)],
+                        |row| row.get::<_, Option<String>>(0),
+                    )
+                    .ok()
+                    .flatten()
+            })
+            .collect();
+        for album_id in album_ids {
+            let earliest: Option<String> = self.db.query_row(
+                "SELECT MIN(created_at) FROM media_file WHERE album_id = :album_id",
+                &[(":album_id", &album_id)],
+                |row| row.get(0),
+            )?;
+            if let Some(earliest) = earliest {
+                self.set_created_at("album", &album_id, &earliest, &mut changes)?;
+            }
+        }
+        sync.albums = changes.len() - sync.media_files;
+        Ok((sync, changes))
+    }
+}
+
+/// Writes each change to `created_at` as CSV, to restore the earlier values if needed.
+pub fn write_date_changes(changes: &[DateChange], config: &Config) -> Result<(), csv::Error> {
+    let mut writer = csv::Writer::from_path(config.info_path(&config.date_added_changes_file))?;
+    for change in changes {
+        writer.serialize(change)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// The date as text, as rusqlite writes it.
+pub fn sql_date(date: DateTime<Utc>) -> String {
+    date.format("%F %T%.f%:z").to_string()
+}
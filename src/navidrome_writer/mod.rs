@@ -1,9 +1,38 @@
+use std::collections::{HashMap, HashSet};
+
 use crate::{
     config::Config,
-    structs::{track::Track, Library},
+    report::{CountSync, TrackSync},
+    structs::{
+        credits::ArtistCredits, duplicates::DuplicateCluster, track::Track, Library, TrackID,
+    },
 };
 use chrono::{DateTime, Utc};
 use rusqlite::{Connection, Result, ToSql};
+use snapshot::{SyncSnapshot, TrackState};
+use unmatched::UnmatchedTrack;
+
+pub mod dates;
+pub mod reconcile;
+pub mod skips;
+pub mod snapshot;
+pub mod tags;
+pub mod unmatched;
+
+pub mod err {
+    #[derive(Debug)]
+    pub enum NavidromeWriter {
+        Sql(rusqlite::Error),
+        NoUser { user: String },
+        MultipleUsers { user: String, ids: Vec<String> },
+    }
+
+    impl From<rusqlite::Error> for NavidromeWriter {
+        fn from(error: rusqlite::Error) -> Self {
+            NavidromeWriter::Sql(error)
+        }
+    }
+}
 
 pub struct TrackMatcher<'t> {
     pub track: &'t Track,
@@ -13,10 +42,38 @@ pub struct TrackMatcher<'t> {
     pub item_id: Option<String>,
 }
 
+/// The values written to Navidrome's annotation table for an item.
+#[derive(Debug, Default, PartialEq)]
+pub struct Annotation {
+    pub play_count: usize,
+    pub play_date: Option<DateTime<Utc>>,
+    pub rating: Option<usize>,
+    pub starred: Option<bool>,
+}
+
+/// How a write to the annotation table compared to what was there before.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnnotationWrite {
+    Inserted,
+    Changed,
+    Unchanged,
+}
+
 pub struct NavidromeWriter {
     pub db: Connection,
 }
 
+/// How the database links artists to the media files they are credited on, which Navidrome does from 0.55.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArtistLinks {
+    /// The `media_file_artists` table, of media file, artist, and role.
+    Table,
+    /// The `participants` JSON column of `media_file`, of artists by role.
+    Participants,
+    /// Neither, so artists are found by name only.
+    Names,
+}
+
 impl Drop for NavidromeWriter {
     fn drop(&mut self) {
         let mut tmp = Connection::open_in_memory().unwrap();
@@ -103,6 +160,17 @@ impl NavidromeWriter {
         Ok(NavidromeWriter { db: connection })
     }
 
+    /// An in-memory copy of the database at `path`, for dry runs, so no file is written to.
+    pub fn scratch(path: &std::path::Path) -> Result<Self, rusqlite::Error> {
+        let mut connection = Connection::open_in_memory()?;
+        connection.restore(
+            rusqlite::DatabaseName::Main,
+            path,
+            None::<fn(rusqlite::backup::Progress)>,
+        )?;
+        Ok(NavidromeWriter { db: connection })
+    }
+
     pub fn item_ids(&self, matcher: &mut TrackMatcher) -> Result<Vec<String>, rusqlite::Error> {
         let mut item_ids: Vec<String> = vec![];
 
@@ -125,8 +193,63 @@ impl NavidromeWriter {
         Ok(item_ids)
     }
 
-    pub fn artist_id(&self, artist: &str) -> Result<Option<String>, rusqlite::Error> {
-        let query_string = "SELECT id, name FROM artist WHERE name = :name";
+    /// The names of the columns of `table`, with none if there is no such table.
+    pub fn columns(&self, table: &str) -> Result<HashSet<String>, rusqlite::Error> {
+        let mut stmt = self
+            .db
+            .prepare("SELECT name FROM pragma_table_info(:table)")?;
+        let columns = stmt
+            .query_map(&[(":table", table)], |row| row.get::<_, String>(0))?
+            .collect::<Result<HashSet<_>, _>>()?;
+        Ok(columns)
+    }
+
+    pub fn artist_links(&self) -> Result<ArtistLinks, rusqlite::Error> {
+        if !self.columns("media_file_artists")?.is_empty() {
+            Ok(ArtistLinks::Table)
+        } else if self.columns("media_file")?.contains("participants") {
+            Ok(ArtistLinks::Participants)
+        } else {
+            Ok(ArtistLinks::Names)
+        }
+    }
+
+    /// The id of the artist credited in `role` ("artist" or "albumartist"), found through the links of artists to media files if the database has them,
+    /// and otherwise by name, ignoring case if there's no exact match.
+    pub fn artist_id(
+        &self,
+        artist: &str,
+        role: &str,
+        links: ArtistLinks,
+    ) -> Result<Option<String>, rusqlite::Error> {
+        let linked = match links {
+            ArtistLinks::Table => Some(
+                "SELECT artist.id AS id FROM artist
+WHERE artist.name = :name COLLATE NOCASE
+AND EXISTS (SELECT 1 FROM media_file_artists WHERE media_file_artists.artist_id = artist.id AND media_file_artists.role = :role)
+ORDER BY artist.name = :name DESC",
+            ),
+            ArtistLinks::Participants => Some(
+                "SELECT DISTINCT json_extract(credit.value, '$.id') AS id, json_extract(credit.value, '$.name') AS credited
+FROM media_file, json_each(media_file.participants, '$.' || :role) AS credit
+WHERE credited = :name COLLATE NOCASE
+ORDER BY credited = :name DESC",
+            ),
+            ArtistLinks::Names => None,
+        };
+        if let Some(query_string) = linked {
+            let mut stmt = self.db.prepare(query_string)?;
+            let mut rows = stmt.query(&[(":name", artist), (":role", role)])?;
+            while let Some(row) = rows.next()? {
+                let id: Option<String> = row.get("id")?;
+                if let Some(found) = id {
+                    return Ok(Some(found));
+                }
+            }
+        }
+
+        let query_string =
+            "SELECT id, name FROM artist WHERE name = :name COLLATE NOCASE ORDER BY name = :name DESC";
 
         let mut stmt = self.db.prepare(query_string)?;
         let mut rows = stmt.query(&[(":name", artist)])?;
@@ -139,16 +262,16 @@ impl NavidromeWriter {
         Ok(None)
     }
 
+    /// The id of the album, as shown under its album artist.
     pub fn album_id(
         &self,
         album: &str,
-        artist_id: &str,
+        album_artist_id: &str,
     ) -> Result<Option<String>, rusqlite::Error> {
-        let query_string =
-            "SELECT id, name, artist_id FROM album WHERE name = :name AND artist_id = :artist_id";
+        let query_string = "SELECT id, name, album_artist_id FROM album WHERE name = :name AND album_artist_id = :album_artist_id";
 
         let mut stmt = self.db.prepare(query_string)?;
-        let mut rows = stmt.query(&[(":name", album), (":artist_id", artist_id)])?;
+        let mut rows = stmt.query(&[(":name", album), (":album_artist_id", album_artist_id)])?;
         while let Some(row) = rows.next()? {
             let id: Option<String> = row.get("id")?;
             if let Some(found) = id {
@@ -175,59 +298,144 @@ VALUES
 )
 ";
 
-    pub fn update_match(
+    const ANNOTATION_SCHEMA: &'static str = "
+SELECT play_count, play_date, rating, starred
+FROM annotation
+WHERE user_id = :user_id AND item_id = :item_id AND item_type = :item_type
+";
+
+    pub fn annotation(
+        &self,
+        user_id: &str,
+        item_id: &str,
+        item_type: &str,
+    ) -> Result<Option<Annotation>, rusqlite::Error> {
+        let mut stmt = self.db.prepare(Self::ANNOTATION_SCHEMA)?;
+        let mut rows = stmt.query(&[
+            (":user_id", user_id),
+            (":item_id", item_id),
+            (":item_type", item_type),
+        ])?;
+        match rows.next()? {
+            Some(row) => Ok(Some(Annotation {
+                play_count: row.get::<_, Option<usize>>("play_count")?.unwrap_or(0),
+                // a date in a format chrono does not recognise is treated as missing
+                play_date: row.get("play_date").unwrap_or(None),
+                rating: row.get("rating")?,
+                starred: row.get("starred")?,
+            })),
+            None => Ok(None),
+        }
+    }
+
+    /// Writes `annotation`, replacing any existing annotation for the item.
+    pub fn write_annotation(
         &self,
-        matcher: &TrackMatcher,
         user_id: &str,
-    ) -> Result<(), rusqlite::Error> {
+        item_id: &str,
+        item_type: &str,
+        annotation: &Annotation,
+    ) -> Result<AnnotationWrite, rusqlite::Error> {
+        let write = match self.annotation(user_id, item_id, item_type)? {
+            None => AnnotationWrite::Inserted,
+            Some(existing) if existing == *annotation => AnnotationWrite::Unchanged,
+            Some(_) => AnnotationWrite::Changed,
+        };
+
         let params: [(&str, &dyn ToSql); 8] = [
             (":user_id", &user_id),
-            (":item_id", &matcher.item_id),
-            (":item_type", &"media_file"),
-            (":play_count", &matcher.track.play_count),
-            (":play_date", &matcher.track.play_date),
-            (":rating", &matcher.track.rating),
-            (
-                ":starred",
-                &(matcher.track.loved || matcher.track.favourited),
-            ),
+            (":item_id", &item_id),
+            (":item_type", &item_type),
+            (":play_count", &annotation.play_count),
+            (":play_date", &annotation.play_date),
+            (":rating", &annotation.rating),
+            (":starred", &annotation.starred),
             (":starred_at", &None::<DateTime<Utc>>),
         ];
 
         let mut stmt = self.db.prepare(Self::UPDATE_SCHEMA)?;
-        match stmt.execute(&params) {
-            Err(e) => {
-                let id = &matcher.item_id;
-                log::error!("Error updating track {id:?}: {e:?}");
-                Ok(())
+        stmt.execute(&params)?;
+        Ok(write)
+    }
+
+    /// Writes the annotation for the track of `matcher`.
+    ///
+    /// With the state of the track at the last sync, plays made in Apple Music since are added to those in Navidrome.
+    /// Otherwise, the play count in Navidrome is replaced.
+    pub fn update_match(
+        &self,
+        matcher: &TrackMatcher,
+        user_id: &str,
+        last_sync: Option<&TrackState>,
+        config: &Config,
+    ) -> Result<AnnotationWrite, rusqlite::Error> {
+        let item_id = matcher.item_id.as_deref().unwrap_or_default();
+        let mut annotation = Annotation {
+            play_count: matcher.track.play_count,
+            play_date: matcher.track.play_date,
+            rating: Some(skips::rating(matcher.track, config)),
+            starred: Some(matcher.track.loved || matcher.track.favourited),
+        };
+        if let Some(last_sync) = last_sync {
+            if let Some(existing) = self.annotation(user_id, item_id, "media_file")? {
+                let new_plays = matcher
+                    .track
+                    .play_count
+                    .saturating_sub(last_sync.play_count);
+                annotation.play_count = existing.play_count + new_plays;
+                annotation.play_date = annotation.play_date.max(existing.play_date);
             }
-            Ok(_) => Ok(()),
         }
+        self.write_annotation(user_id, item_id, "media_file", &annotation)
     }
 
+    /// Writes the play count of each credited artist, and of each album under its album artist.
     pub fn set_artist_album_counts(
         &self,
         library: &Library,
         user_id: &str,
-    ) -> Result<(), rusqlite::Error> {
-        'artist_loop: for (artist, counts) in &library.counts {
-            match self.artist_id(artist.as_str()) {
+        credits: &ArtistCredits,
+    ) -> Result<CountSync, rusqlite::Error> {
+        let mut sync = CountSync::default();
+        let links = self.artist_links()?;
+        for (artist, count) in library.credited_artist_playcounts(credits) {
+            match self.artist_id(&artist, "artist", links) {
                 Ok(Some(artist_id)) => {
-                    self.update_artist(&artist_id, counts.count, user_id)?;
-                    for (album, count) in &counts.albums {
-                        self.update_album(album, *count, &artist_id, user_id)?;
-                    }
+                    sync.artists
+                        .record(self.update_artist(&artist_id, count, user_id)?);
                 }
                 Ok(None) => {
                     log::trace!("Could not find an artist in the navidrome database: {artist}");
-                    continue 'artist_loop;
+                    sync.artists_missing += 1;
                 }
                 Err(e) => {
                     log::error!("Failed to update artist: {:?}\n{e:?}", &artist);
                 }
             }
         }
-        Ok(())
+
+        for (album_artist, counts) in &library.counts {
+            match self.artist_id(album_artist, "albumartist", links) {
+                Ok(Some(artist_id)) => {
+                    for (album, count) in &counts.albums {
+                        match self.update_album(album, count.count, &artist_id, user_id)? {
+                            Some(write) => sync.albums.record(write),
+                            None => sync.albums_missing += 1,
+                        }
+                    }
+                }
+                Ok(None) => {
+                    log::trace!(
+                        "Could not find an album artist in the navidrome database: {album_artist}"
+                    );
+                    sync.albums_missing += counts.albums.len();
+                }
+                Err(e) => {
+                    log::error!("Failed to update albums of: {:?}\n{e:?}", &album_artist);
+                }
+            }
+        }
+        Ok(sync)
     }
 
     pub fn update_artist(
@@ -235,61 +443,50 @@ VALUES
         artist_id: &str,
         count: usize,
         user_id: &str,
-    ) -> Result<(), rusqlite::Error> {
-        let params: [(&str, &dyn ToSql); 8] = [
-            (":user_id", &user_id),
-            (":item_id", &artist_id),
-            (":item_type", &"artist"),
-            (":play_count", &count),
-            (":play_date", &None::<DateTime<Utc>>),
-            (":rating", &None::<usize>),
-            (":starred", &None::<bool>),
-            (":starred_at", &None::<DateTime<Utc>>),
-        ];
-
-        let mut stmt = self.db.prepare(Self::UPDATE_SCHEMA)?;
-        stmt.execute(&params)?;
-        Ok(())
+    ) -> Result<AnnotationWrite, rusqlite::Error> {
+        let annotation = Annotation {
+            play_count: count,
+            ..Default::default()
+        };
+        self.write_annotation(user_id, artist_id, "artist", &annotation)
     }
 
+    /// Writes the play count of an album, if the album can be found.
     pub fn update_album(
         &self,
         album: &str,
         count: usize,
         artist_id: &str,
         user_id: &str,
-    ) -> Result<(), rusqlite::Error> {
+    ) -> Result<Option<AnnotationWrite>, rusqlite::Error> {
         match self.album_id(album, artist_id)? {
             Some(album_id) => {
-                let params: [(&str, &dyn ToSql); 8] = [
-                    (":user_id", &user_id),
-                    (":item_id", &album_id),
-                    (":item_type", &"album"),
-                    (":play_count", &count),
-                    (":play_date", &None::<DateTime<Utc>>),
-                    (":rating", &None::<usize>),
-                    (":starred", &None::<bool>),
-                    (":starred_at", &None::<DateTime<Utc>>),
-                ];
-                let mut stmt = self.db.prepare(Self::UPDATE_SCHEMA)?;
-                if let Err(e) = stmt.execute(&params) {
-                    log::error!("Error updating album: {e:?}");
+                let annotation = Annotation {
+                    play_count: count,
+                    ..Default::default()
+                };
+                match self.write_annotation(user_id, &album_id, "album", &annotation) {
+                    Ok(write) => Ok(Some(write)),
+                    Err(e) => {
+                        log::error!("Error updating album: {e:?}");
+                        Ok(None)
+                    }
                 }
             }
             None => {
                 log::trace!("Could not find an album in the navidrome database: {album}");
+                Ok(None)
             }
         }
-
-        Ok(())
     }
 
     pub fn user_ids(&self, user: &str) -> Result<Vec<String>, rusqlite::Error> {
         let mut ids = vec![];
 
-        let query_string = format!("SELECT id, user_name FROM user WHERE user_name = '{user}'");
-        let mut stmt = self.db.prepare(&query_string)?;
-        let mut rows = stmt.query([])?;
+        let mut stmt = self
+            .db
+            .prepare("SELECT id, user_name FROM user WHERE user_name = :user")?;
+        let mut rows = stmt.query(&[(":user", user)])?;
         while let Some(row) = rows.next()? {
             let id = row.get("id")?;
             ids.push(id);
@@ -298,18 +495,109 @@ VALUES
         Ok(ids)
     }
 
-    pub fn update_tracks(&self, library: &Library, user_id: &str, config: &Config) {
+    /// The media file of the first duplicate of the canonical track `id` found in the navidrome database,
+    /// so the merged plays are kept when only a duplicate is in Navidrome.
+    fn duplicate_item_id(
+        &self,
+        library: &Library,
+        clusters: &[DuplicateCluster],
+        id: &TrackID,
+    ) -> Result<Option<String>, rusqlite::Error> {
+        let Some(cluster) = clusters.iter().find(|cluster| &cluster.canonical == id) else {
+            return Ok(None);
+        };
+        for duplicate in cluster
+            .duplicates
+            .iter()
+            .filter_map(|id| library.tracks.get(id))
+        {
+            let mut matcher = TrackMatcher::from_track(duplicate);
+            if self.item_ids(&mut matcher)?.len() == 1 {
+                return Ok(matcher.item_id);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Matches and annotates each track of `library`, recording the tracks written in `snapshot`.
+    ///
+    /// For an incremental sync, tracks unchanged since the sync recorded in `snapshot` are skipped.
+    pub fn update_tracks(
+        &self,
+        library: &Library,
+        user_id: &str,
+        config: &Config,
+        snapshot: &mut SyncSnapshot,
+    ) -> Result<TrackSync, err::NavidromeWriter> {
+        let mut sync = TrackSync::default();
         let mut failed_matches = vec![];
         let mut multiple_matches = vec![];
+
+        let last_sync = match config.incremental_sync {
+            true => std::mem::take(&mut snapshot.tracks),
+            false => HashMap::default(),
+        };
+        *snapshot = SyncSnapshot {
+            library_date: library.date,
+            ..Default::default()
+        };
+
+        let clusters = match config.merge_duplicates {
+            true => library.duplicates(config.duplicate_tolerance()),
+            false => vec![],
+        };
+        let (merged, merged_away) = library.merge_duplicates(&clusters);
+
+        if config.export_skips {
+            self.create_skip_table()?;
+        }
+
         for track in library.tracks.values() {
+            if merged_away.contains(&track.id) {
+                sync.merged += 1;
+                continue;
+            }
+            let track = merged.get(&track.id).unwrap_or(track);
+            let state = TrackState::from_track(track);
+            let last_state = last_sync.get(&track.persistent_id);
+            if last_state == Some(&state) {
+                sync.skipped += 1;
+                snapshot.tracks.insert(track.persistent_id.clone(), state);
+                continue;
+            }
+
             let mut matcher = TrackMatcher::from_track(track);
-            let ids = self.item_ids(&mut matcher).unwrap();
+            let mut ids = self.item_ids(&mut matcher)?;
+            if ids.is_empty() && merged.contains_key(&track.id) {
+                if let Some(item_id) = self.duplicate_item_id(library, &clusters, &track.id)? {
+                    log::debug!(
+                        "Writing the merged plays of {} to the media file of a duplicate",
+                        track.id
+                    );
+                    matcher.item_id = Some(item_id.clone());
+                    ids.push(item_id);
+                }
+            }
             match ids.len() {
                 0 => failed_matches.push(track), // missing track
                 1 => {
                     // unique track
-                    match self.update_match(&matcher, user_id) {
-                        Ok(_) => {}
+                    sync.matched += 1;
+                    if config.export_skips && track.skip_count > 0 {
+                        let item_id = matcher.item_id.as_deref().unwrap_or_default();
+                        match self.write_skips(user_id, item_id, track) {
+                            Ok(_) => sync.skips_written += 1,
+                            Err(e) => {
+                                log::error!("Failed to write skips: {:?}\n{e:?}", &track.title)
+                            }
+                        }
+                    }
+                    match self.update_match(&matcher, user_id, last_state, config) {
+                        Ok(write) => {
+                            sync.annotations.record(write);
+                            sync.skip_rated += skips::skip_rated(track, config) as usize;
+                            snapshot.tracks.insert(track.persistent_id.clone(), state);
+                        }
                         Err(e) => {
                             log::error!("Failed to update track: {:?}\n{e:?}", &track.title);
                         }
@@ -319,8 +607,21 @@ VALUES
             }
         }
 
+        sync.unmatched = failed_matches.len();
+        sync.ambiguous = multiple_matches.len();
+
         if !failed_matches.is_empty() {
-            match write_failed_matches(failed_matches, config) {
+            let mut unmatched = vec![];
+            for track in failed_matches {
+                match self.unmatched_track(track, config.nearest_candidates) {
+                    Ok(track) => unmatched.push(track),
+                    Err(e) => log::error!(
+                        "Error finding the nearest tracks in the navidrome database to {}: {e:?}",
+                        track.id
+                    ),
+                }
+            }
+            match write_failed_matches(&unmatched, config) {
             Ok(_) => {},
             Err(e) => log::warn!("Some tracks from Apple Music could not be matched to a track in the navidrome database.
 An error occurred when attempting to write these to a file: {e:?}")
@@ -333,40 +634,30 @@ An error occurred when attempting to write these to a file: {e:?}")
 An error occurred when attempting to write these to a file: {e:?}")
 }
         }
+        Ok(sync)
     }
 
-    pub fn get_navidrome_user_id(&self, config: &Config) -> String {
+    pub fn get_navidrome_user_id(&self, config: &Config) -> Result<String, err::NavidromeWriter> {
         match &config.navidrome_user_id {
             Some(id) => {
                 log::info!("user_id from config: {id}");
-                id.clone()
+                Ok(id.clone())
             }
             None => {
                 let user = &config.navidrome_user;
-                let ids = match self.user_ids(&config.navidrome_user) {
-                    Ok(v) => v,
-                    Err(e) => {
-                        log::error!("Failed to get possible user ids from the navidrome database");
-                        log::error!("{e:?}");
-                        std::process::exit(1);
-                    }
-                };
+                let ids = self.user_ids(&config.navidrome_user)?;
                 match &ids[..] {
-                    [] => {
-                        log::error!("No user \"{user}\" found.");
-                        std::process::exit(1);
-                    }
+                    [] => Err(err::NavidromeWriter::NoUser {
+                        user: user.to_owned(),
+                    }),
                     [unique] => {
                         log::info!("User \"{user}\" found with id: {unique}");
-                        unique.to_owned()
-                    }
-                    _ => {
-                        log::error!("Multiple ids found for user \"{user}\".");
-                        log::error!("Please add a database id to the config file");
-                        log::error!("For example, a line which reads (though your ):");
-                        log::error!("navidrome_user_id = \"d868f4b6-1d16-4c05-ae0c-4aca4ef42788\"");
-                        std::process::exit(1);
+                        Ok(unique.to_owned())
                     }
+                    _ => Err(err::NavidromeWriter::MultipleUsers {
+                        user: user.to_owned(),
+                        ids,
+                    }),
                 }
             }
         }
@@ -374,7 +665,7 @@ An error occurred when attempting to write these to a file: {e:?}")
 }
 
 fn write_failed_matches(
-    failed_matches: Vec<&Track>,
+    failed_matches: &[UnmatchedTrack],
     config: &Config,
 ) -> Result<(), std::io::Error> {
     let mut fail_match_file = std::fs::File::create(config.info_path(&config.no_match_file))?;
@@ -382,6 +673,7 @@ fn write_failed_matches(
         &mut fail_match_file,
         serde_json::to_string_pretty(&failed_matches)?.as_bytes(),
     );
+    unmatched::write_unmatched_csv(failed_matches, &config.info_path(&config.no_match_csv_file))?;
     log::warn!(
         "Some tracks from Apple Music could not be matched to a track in the navidrome database.
 A file containing these tracks has been made."
@@ -405,3 +697,47 @@ A file containing these tracks has been made."
     );
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::Connection;
+
+    use super::*;
+
+    #[test]
+    fn finds_users_by_name() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "CREATE TABLE user (id VARCHAR, user_name VARCHAR);
+INSERT INTO user VALUES ('u1', 'user');
+INSERT INTO user VALUES ('u2', 'o''brien');",
+        )
+        .unwrap();
+        let writer = NavidromeWriter { db };
+        assert_eq!(writer.user_ids("user").unwrap(), ["u1"]);
+        assert_eq!(writer.user_ids("o'brien").unwrap(), ["u2"]);
+        assert!(writer.user_ids("' OR '1' = '1").unwrap().is_empty());
+    }
+
+    #[test]
+    fn finds_albums_under_the_album_artist() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "CREATE TABLE album (id VARCHAR, name VARCHAR, artist_id VARCHAR, album_artist_id VARCHAR);
+INSERT INTO album VALUES ('al1', 'Hits', 'a2', 'a3');
+INSERT INTO album VALUES ('al2', 'First', 'a1', 'a1');",
+        )
+        .unwrap();
+        let writer = NavidromeWriter { db };
+        assert_eq!(
+            writer.album_id("Hits", "a3").unwrap().as_deref(),
+            Some("al1")
+        );
+        // the track artist of a compilation does not find it
+        assert_eq!(writer.album_id("Hits", "a2").unwrap(), None);
+        assert_eq!(
+            writer.album_id("First", "a1").unwrap().as_deref(),
+            Some("al2")
+        );
+    }
+}
@@ -0,0 +1,277 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    navidrome_reader::{apple_rating, navidrome_rating},
+    report::{Reconciliation, TrackSync},
+    structs::{track::Track, Library},
+};
+
+use super::{err, Annotation, NavidromeWriter, TrackMatcher};
+
+/// How to choose between the Apple Music and Navidrome values of a track.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Rule {
+    /// The larger value, or starred if either is starred.
+    #[default]
+    Max,
+    /// The value from the side with the most recent play.
+    LatestWins,
+    PreferApple,
+    PreferNavidrome,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Side {
+    Apple,
+    Navidrome,
+}
+
+impl Rule {
+    fn choose<T: Ord>(self, apple: T, navidrome: T, latest: Side) -> T {
+        match (self, latest) {
+            (Rule::Max, _) => std::cmp::max(apple, navidrome),
+            (Rule::PreferApple, _) | (Rule::LatestWins, Side::Apple) => apple,
+            (Rule::PreferNavidrome, _) | (Rule::LatestWins, Side::Navidrome) => navidrome,
+        }
+    }
+}
+
+/// A change Apple Music would need for a track to agree with the reconciled values.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AppleChange {
+    pub persistent_id: String,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub title: Option<String>,
+    pub field: String,
+    pub apple: String,
+    pub reconciled: String,
+}
+
+fn show<T: std::fmt::Debug>(value: &Option<T>) -> String {
+    match value {
+        Some(value) => format!("{value:?}"),
+        None => String::new(),
+    }
+}
+
+/// The values agreed from both sides, using the rules of `config`.
+///
+/// The play date is always the most recent of the two.
+/// Ratings are compared, and the rating returned, on the Apple Music scale of 0 to 100.
+fn reconcile(track: &Track, navidrome: &Annotation, config: &Config) -> Annotation {
+    let latest = match navidrome.play_date > track.play_date {
+        true => Side::Navidrome,
+        false => Side::Apple,
+    };
+    Annotation {
+        play_count: config.reconcile_play_count.choose(
+            track.play_count,
+            navidrome.play_count,
+            latest,
+        ),
+        play_date: std::cmp::max(track.play_date, navidrome.play_date),
+        rating: Some(config.reconcile_rating.choose(
+            track.rating,
+            apple_rating(navidrome.rating.unwrap_or(0)),
+            latest,
+        )),
+        starred: Some(config.reconcile_starred.choose(
+            track.loved || track.favourited,
+            navidrome.starred.unwrap_or(false),
+            latest,
+        )),
+    }
+}
+
+fn apple_changes(track: &Track, reconciled: &Annotation) -> Vec<AppleChange> {
+    let change = |field: &str, apple: String, reconciled: String| AppleChange {
+        persistent_id: track.persistent_id.clone(),
+        artist: track.artist.clone(),
+        album: track.album_title.clone(),
+        title: track.title.clone(),
+        field: field.to_owned(),
+        apple,
+        reconciled,
+    };
+    let mut changes = vec![];
+    if reconciled.play_count != track.play_count {
+        changes.push(change(
+            "play_count",
+            track.play_count.to_string(),
+            reconciled.play_count.to_string(),
+        ));
+    }
+    if reconciled.play_date != track.play_date {
+        changes.push(change(
+            "play_date",
+            show(&track.play_date),
+            show(&reconciled.play_date),
+        ));
+    }
+    if reconciled.rating != Some(track.rating) {
+        changes.push(change(
+            "rating",
+            track.rating.to_string(),
+            show(&reconciled.rating),
+        ));
+    }
+    if reconciled.starred != Some(track.loved || track.favourited) {
+        changes.push(change(
+            "loved",
+            (track.loved || track.favourited).to_string(),
+            show(&reconciled.starred),
+        ));
+    }
+    changes
+}
+
+impl NavidromeWriter {
+    /// Writes the reconciled values of each matched track to Navidrome, returning the changes Apple Music would need.
+    pub fn reconcile_tracks(
+        &self,
+        library: &Library,
+        user_id: &str,
+        config: &Config,
+    ) -> Result<(Reconciliation, Vec<AppleChange>), err::NavidromeWriter> {
+        let mut tracks = TrackSync::default();
+        let mut changes = vec![];
+        for track in library.tracks.values() {
+            let mut matcher = TrackMatcher::from_track(track);
+            let ids = self.item_ids(&mut matcher)?;
+            match ids.len() {
+                0 => tracks.unmatched += 1,
+                1 => {
+                    tracks.matched += 1;
+                    let item_id = matcher.item_id.as_deref().unwrap_or_default();
+                    let navidrome = self
+                        .annotation(user_id, item_id, "media_file")?
+                        .unwrap_or_default();
+                    let reconciled = reconcile(track, &navidrome, config);
+                    let written = Annotation {
+                        rating: reconciled.rating.map(navidrome_rating),
+                        ..reconciled
+                    };
+                    let write = self.write_annotation(user_id, item_id, "media_file", &written)?;
+                    tracks.annotations.record(write);
+                    changes.extend(apple_changes(track, &reconciled));
+                }
+                _ => tracks.ambiguous += 1,
+            }
+        }
+        changes.sort_by(|a, b| {
+            (&a.artist, &a.album, &a.title, &a.field)
+                .cmp(&(&b.artist, &b.album, &b.title, &b.field))
+        });
+        let reconciliation = Reconciliation {
+            tracks,
+            apple_changes: changes.len(),
+        };
+        Ok((reconciliation, changes))
+    }
+}
+
+/// Writes the changes Apple Music would need as JSON and as CSV, with a row for each change.
+pub fn write_apple_changes(changes: &[AppleChange], config: &Config) -> Result<(), csv::Error> {
+    let mut file = std::fs::File::create(config.info_path(&config.apple_changes_file))?;
+    std::io::Write::write_all(
+        &mut file,
+        serde_json::to_string_pretty(changes)
+            .map_err(std::io::Error::from)?
+            .as_bytes(),
+    )?;
+    let mut writer = csv::Writer::from_path(config.info_path(&config.apple_changes_csv_file))?;
+    for change in changes {
+        writer.serialize(change)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{date, track};
+
+    fn config(rule: Rule) -> Config {
+        Config {
+            reconcile_play_count: rule,
+            reconcile_rating: rule,
+            reconcile_starred: rule,
+            ..Default::default()
+        }
+    }
+
+    /// Played more in Apple Music, but played last and rated higher in Navidrome.
+    fn sides() -> (Track, Annotation) {
+        let apple = Track {
+            play_count: 10,
+            play_date: Some(date("2024-01-01T00:00:00Z")),
+            rating: 40,
+            loved: true,
+            ..track("1")
+        };
+        let navidrome = Annotation {
+            play_count: 4,
+            play_date: Some(date("2024-06-01T00:00:00Z")),
+            rating: Some(4),
+            starred: Some(false),
+        };
+        (apple, navidrome)
+    }
+
+    #[test]
+    fn each_rule() {
+        let (apple, navidrome) = sides();
+        for (rule, play_count, rating, starred) in [
+            (Rule::Max, 10, 80, true),
+            (Rule::LatestWins, 4, 80, false),
+            (Rule::PreferApple, 10, 40, true),
+            (Rule::PreferNavidrome, 4, 80, false),
+        ] {
+            let reconciled = reconcile(&apple, &navidrome, &config(rule));
+            assert_eq!(reconciled.play_count, play_count, "{rule:?}");
+            assert_eq!(reconciled.rating, Some(rating), "{rule:?}");
+            assert_eq!(reconciled.starred, Some(starred), "{rule:?}");
+            assert_eq!(reconciled.play_date, navidrome.play_date, "{rule:?}");
+        }
+    }
+
+    #[test]
+    fn latest_wins_apple_when_played_last_there() {
+        let (apple, navidrome) = sides();
+        let apple = Track {
+            play_date: Some(date("2025-01-01T00:00:00Z")),
+            ..apple
+        };
+        let reconciled = reconcile(&apple, &navidrome, &config(Rule::LatestWins));
+        assert_eq!(reconciled.play_count, 10);
+        assert_eq!(reconciled.rating, Some(40));
+    }
+
+    #[test]
+    fn ratings_on_one_scale() {
+        let (apple, navidrome) = sides();
+        // 4 stars in Navidrome is more than 2 stars (40) in Apple Music
+        let reconciled = reconcile(&apple, &navidrome, &config(Rule::Max));
+        assert_eq!(reconciled.rating.map(navidrome_rating), Some(4));
+
+        let changes = apple_changes(&apple, &reconciled);
+        let rating = changes.iter().find(|c| c.field == "rating").unwrap();
+        assert_eq!(
+            (rating.apple.as_str(), rating.reconciled.as_str()),
+            ("40", "80")
+        );
+    }
+
+    #[test]
+    fn navidrome_ratings() {
+        assert_eq!(navidrome_rating(0), 0);
+        assert_eq!(navidrome_rating(20), 1);
+        assert_eq!(navidrome_rating(50), 3);
+        assert_eq!(navidrome_rating(100), 5);
+        assert_eq!(navidrome_rating(apple_rating(4)), 4);
+    }
+}
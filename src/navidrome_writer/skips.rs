@@ -0,0 +1,122 @@
+use rusqlite::ToSql;
+
+use crate::{config::Config, structs::track::Track};
+
+use super::NavidromeWriter;
+
+/// Navidrome has no column for skips, so they are kept in a table of their own.
+pub const SKIP_TABLE: &str = "apple_music_skips";
+
+impl NavidromeWriter {
+    pub fn create_skip_table(&self) -> Result<(), rusqlite::Error> {
+        self.db.execute(
+            &format!(
+                "CREATE TABLE IF NOT EXISTS {SKIP_TABLE} (
+user_id VARCHAR(255) NOT NULL,
+item_id VARCHAR(255) NOT NULL,
+skip_count INTEGER NOT NULL DEFAULT 0,
+skip_date DATETIME,
+PRIMARY KEY (user_id, item_id)
+)"
+            ),
+            [],
+        )?;
+        Ok(())
+    }
+
+    /// Writes the skips of `track` for the media file `item_id`, replacing any skips written before.
+    pub fn write_skips(
+        &self,
+        user_id: &str,
+        item_id: &str,
+        track: &Track,
+    ) -> Result<(), rusqlite::Error> {
+        let params: [(&str, &dyn ToSql); 4] = [
+            (":user_id", &user_id),
+            (":item_id", &item_id),
+            (":skip_count", &track.skip_count),
+            (":skip_date", &track.skip_date),
+        ];
+        self.db.execute(
+            &format!(
+                "INSERT OR REPLACE INTO {SKIP_TABLE} (user_id, item_id, skip_count, skip_date)
+VALUES (:user_id, :item_id, :skip_count, :skip_date)"
+            ),
+            &params,
+        )?;
+        Ok(())
+    }
+}
+
+/// Whether an unrated track has been skipped often enough to be given `skip_rating_stars`.
+pub fn skip_rated(track: &Track, config: &Config) -> bool {
+    let Some(ratio) = config.skip_rating_ratio else {
+        return false;
+    };
+    track.rating == 0
+        && track.skip_count >= config.skip_rating_min_skips
+        && track.skip_count as f64 >= ratio * track.play_count.max(1) as f64
+}
+
+/// The rating written for `track`, with tracks which are mostly skipped given `skip_rating_stars`, on Navidrome's scale of 0 to 5.
+pub fn rating(track: &Track, config: &Config) -> usize {
+    match skip_rated(track, config) {
+        true => config.skip_rating_stars.min(5),
+        false => track.rating,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::track;
+
+    fn config() -> Config {
+        Config {
+            skip_rating_ratio: Some(2.0),
+            skip_rating_min_skips: 5,
+            skip_rating_stars: 1,
+            ..Default::default()
+        }
+    }
+
+    fn skipped(play_count: usize, skip_count: usize, rating: usize) -> Track {
+        Track {
+            play_count,
+            skip_count,
+            rating,
+            ..track("1")
+        }
+    }
+
+    #[test]
+    fn rates_mostly_skipped_tracks() {
+        let config = config();
+        assert!(skip_rated(&skipped(3, 6, 0), &config));
+        assert!(skip_rated(&skipped(0, 5, 0), &config));
+        // fewer than twice as many skips as plays
+        assert!(!skip_rated(&skipped(4, 7, 0), &config));
+        // fewer than the minimum skips
+        assert!(!skip_rated(&skipped(0, 4, 0), &config));
+        // rated in Apple Music
+        assert!(!skip_rated(&skipped(0, 9, 60), &config));
+        // off without a ratio
+        let off = Config {
+            skip_rating_ratio: None,
+            ..config
+        };
+        assert!(!skip_rated(&skipped(0, 9, 0), &off));
+    }
+
+    #[test]
+    fn writes_stars_for_navidrome() {
+        let config = config();
+        assert_eq!(rating(&skipped(0, 9, 0), &config), 1);
+        assert_eq!(rating(&skipped(9, 0, 60), &config), 60);
+        let many = Config {
+            skip_rating_stars: 9,
+            ..config
+        };
+        assert_eq!(rating(&skipped(0, 9, 0), &many), 5);
+    }
+}
@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::structs::{track::Track, Library};
+
+/// The values of a track which are written to Navidrome.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrackState {
+    pub play_count: usize,
+    pub rating: usize,
+    pub loved: bool,
+    #[serde(default)]
+    pub skip_count: usize,
+}
+
+impl TrackState {
+    pub fn from_track(track: &Track) -> Self {
+        TrackState {
+            play_count: track.play_count,
+            rating: track.rating,
+            loved: track.loved || track.favourited,
+            skip_count: track.skip_count,
+        }
+    }
+}
+
+/// The state of each track written by the last successful sync, keyed by persistent id.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SyncSnapshot {
+    pub library_date: DateTime<Utc>,
+    pub tracks: HashMap<String, TrackState>,
+}
+
+impl SyncSnapshot {
+    /// Reads a snapshot, with an empty snapshot if there is no file at `path`.
+    pub fn from_file(path: &Path) -> Result<Self, std::io::Error> {
+        match std::fs::read_to_string(path) {
+            Ok(json) => Ok(serde_json::from_str(&json)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(SyncSnapshot::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Whether the snapshot is of a later library than `library`, whose plays an incremental sync would then undo.
+    pub fn is_later_than(&self, library: &Library) -> bool {
+        self.library_date > library.date
+    }
+
+    pub fn json_export(&self, path: &Path) -> Result<(), std::io::Error> {
+        let snapshot_json = serde_json::to_string(self)?;
+        let mut file = std::fs::File::create(path)?;
+        std::io::Write::write_all(&mut file, snapshot_json.as_bytes())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{date, library, track};
+
+    fn snapshot() -> SyncSnapshot {
+        let mut snapshot = SyncSnapshot {
+            library_date: date("2024-02-01T00:00:00Z"),
+            ..Default::default()
+        };
+        snapshot.tracks.insert(
+            "P1".to_owned(),
+            TrackState::from_track(&Track {
+                play_count: 3,
+                loved: true,
+                ..track("1")
+            }),
+        );
+        snapshot
+    }
+
+    #[test]
+    fn refuses_an_older_library() {
+        let at = |text: &str| Library {
+            date: date(text),
+            ..library([])
+        };
+        assert!(snapshot().is_later_than(&at("2024-01-01T00:00:00Z")));
+        assert!(!snapshot().is_later_than(&at("2024-02-01T00:00:00Z")));
+        assert!(!snapshot().is_later_than(&at("2024-03-01T00:00:00Z")));
+        assert!(!SyncSnapshot::default().is_later_than(&at("2024-01-01T00:00:00Z")));
+    }
+
+    #[test]
+    fn reads_what_was_written() {
+        let path = std::env::temp_dir().join(format!("an_snapshot_{}.json", std::process::id()));
+        snapshot().json_export(&path).unwrap();
+        let read = SyncSnapshot::from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read.library_date, snapshot().library_date);
+        assert_eq!(read.tracks, snapshot().tracks);
+    }
+
+    #[test]
+    fn empty_without_a_file() {
+        let path = std::env::temp_dir().join("an_snapshot_missing.json");
+        let snapshot = SyncSnapshot::from_file(&path).unwrap();
+        assert!(snapshot.tracks.is_empty());
+        assert_eq!(snapshot.library_date, DateTime::<Utc>::default());
+    }
+}
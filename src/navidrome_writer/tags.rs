@@ -0,0 +1,436 @@
+use std::fmt::Write as _;
+
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    navidrome_reader::replace_folder,
+    report::TagSync,
+    structs::{track::Track, Library},
+};
+
+use super::{err, NavidromeWriter, TrackMatcher};
+
+/// A field of a track, with the `media_file` column and the kid3 frame it is kept in.
+pub struct TagField {
+    pub name: &'static str,
+    pub column: &'static str,
+    pub frame: &'static str,
+    pub value: fn(&Track) -> Option<&String>,
+}
+
+/// The fields compared with Navidrome, where the database has a column for them.
+pub const TAG_FIELDS: [TagField; 5] = [
+    TagField {
+        name: "genre",
+        column: "genre",
+        frame: "Genre",
+        value: |t| t.genre.as_ref(),
+    },
+    TagField {
+        name: "grouping",
+        column: "grouping",
+        frame: "Grouping",
+        value: |t| t.grouping.as_ref(),
+    },
+    TagField {
+        name: "comments",
+        column: "comment",
+        frame: "Comment",
+        value: |t| t.comments.as_ref(),
+    },
+    TagField {
+        name: "work",
+        column: "work",
+        frame: "Work",
+        value: |t| t.work.as_ref(),
+    },
+    TagField {
+        name: "movement_title",
+        column: "movement_name",
+        frame: "Movement Name",
+        value: |t| t.movement_title.as_ref(),
+    },
+];
+
+/// A field whose value in Apple Music differs from the value in Navidrome.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TagDifference {
+    pub persistent_id: String,
+    pub item_id: String,
+    /// The path of the file, as Navidrome has it.
+    pub path: Option<String>,
+    pub field: String,
+    pub apple: Option<String>,
+    pub navidrome: Option<String>,
+}
+
+/// Empty values are taken to be missing.
+fn present(value: Option<String>) -> Option<String> {
+    value.filter(|value| !value.trim().is_empty())
+}
+
+/// Where the database keeps the genres of media files, besides the genre column.
+#[derive(Clone, Copy)]
+struct GenreLinks {
+    /// The `genre` and `media_file_genres` tables, before Navidrome 0.55.
+    table: bool,
+    /// The `tag` table and the `tags` JSON column of `media_file`, from Navidrome 0.55.
+    tags: bool,
+}
+
+impl NavidromeWriter {
+    /// Links the media file to the genre `value` only, where the database keeps genres apart from the genre column.
+    ///
+    /// Genres are linked only if Navidrome already has them, as their ids are made by Navidrome.
+    fn apply_genre(
+        &self,
+        item_id: &str,
+        value: &str,
+        links: GenreLinks,
+    ) -> Result<(), rusqlite::Error> {
+        let mut known = false;
+        if links.table {
+            let genre_id: Option<String> = self
+                .db
+                .query_row(
+                    "SELECT id FROM genre WHERE name = :name COLLATE NOCASE",
+                    &[(":name", value)],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            if let Some(genre_id) = genre_id {
+                self.db.execute(
+                    "DELETE FROM media_file_genres WHERE media_file_id = :id",
+                    &[(":id", item_id)],
+                )?;
+                self.db.execute(
+                    "INSERT INTO media_file_genres (media_file_id, genre_id) VALUES (:id, :genre_id)",
+                    &[(":id", item_id), (":genre_id", &genre_id)],
+                )?;
+                known = true;
+            }
+        }
+        if links.tags {
+            let tag: Option<(String, String)> = self
+                .db
+                .query_row(
+                    "SELECT id, tag_value FROM tag WHERE tag_name = 'genre' AND tag_value = :value COLLATE NOCASE",
+                    &[(":value", value)],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .optional()?;
+            if let Some((tag_id, tag_value)) = tag {
+                self.db.execute(
+                    "UPDATE media_file SET tags = json_set(coalesce(tags, '{}'), '$.genre', json_array(json_object('id', :tag_id, 'value', :value))) WHERE id = :id",
+                    &[(":tag_id", &tag_id), (":value", &tag_value), (":id", &item_id.to_owned())],
+                )?;
+                known = true;
+            }
+        }
+        if !known && (links.table || links.tags) {
+            log::info!(
+                "Navidrome has no genre {value}, so only the genre column of {item_id} is changed"
+            );
+        }
+        Ok(())
+    }
+
+    /// The differences between the tags of each matched track and its media file, applying the Apple Music values if `apply`.
+    pub fn tag_differences(
+        &self,
+        library: &Library,
+        apply: bool,
+    ) -> Result<(TagSync, Vec<TagDifference>), err::NavidromeWriter> {
+        let columns = self.columns("media_file")?;
+        let fields: Vec<&TagField> = TAG_FIELDS
+            .iter()
+            .filter(|field| match columns.contains(field.column) {
+                true => true,
+                false => {
+                    log::info!(
+                        "The navidrome database has no {} column, so {} is not compared",
+                        field.column,
+                        field.name
+                    );
+                    false
+                }
+            })
+            .collect();
+        let mut sync = TagSync::default();
+        let mut differences = vec![];
+        if fields.is_empty() {
+            return Ok((sync, differences));
+        }
+        let genre_links = GenreLinks {
+            table: !self.columns("media_file_genres")?.is_empty()
+                && !self.columns("genre")?.is_empty(),
+            tags: columns.contains("tags") && !self.columns("tag")?.is_empty(),
+        };
+        let path_column = match columns.contains("path") {
+            true => "path",
+            false => "NULL",
+        };
+        let select = format!(
+            "SELECT {path_column}, {} FROM media_file WHERE id = :id",
+            fields
+                .iter()
+                .map(|field| field.column)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+
+        let mut tracks: Vec<&Track> = library.tracks.values().collect();
+        tracks.sort_by(|a, b| a.id.cmp(&b.id));
+        for track in tracks {
+            let mut matcher = TrackMatcher::from_track(track);
+            if self.item_ids(&mut matcher)?.len() != 1 {
+                sync.unmatched += 1;
+                continue;
+            }
+            let item_id = matcher.item_id.clone().unwrap_or_default();
+            let (path, navidrome): (Option<String>, Vec<Option<String>>) =
+                self.db.query_row(&select, &[(":id", &item_id)], |row| {
+                    let values = (1..=fields.len())
+                        .map(|index| row.get::<_, Option<String>>(index))
+                        .collect::<Result<_, _>>()?;
+                    Ok((row.get(0)?, values))
+                })?;
+            sync.compared += 1;
+
+            for (field, navidrome) in fields.iter().zip(navidrome) {
+                let apple = present((field.value)(track).cloned());
+                let navidrome = present(navidrome);
+                if apple == navidrome {
+                    continue;
+                }
+                // a value missing from Apple Music is listed, but not cleared in Navidrome
+                if let (true, Some(value)) = (apply, &apple) {
+                    self.db.execute(
+                        &format!(
+                            "UPDATE media_file SET {} = :value WHERE id = :id",
+                            field.column
+                        ),
+                        &[(":value", value), (":id", &item_id)],
+                    )?;
+                    if field.name == "genre" {
+                        self.apply_genre(&item_id, value, genre_links)?;
+                    }
+                    sync.applied += 1;
+                }
+                differences.push(TagDifference {
+                    persistent_id: track.persistent_id.clone(),
+                    item_id: item_id.clone(),
+                    path: path.clone(),
+                    field: field.name.to_owned(),
+                    apple,
+                    navidrome,
+                });
+            }
+        }
+        sync.differences = differences.len();
+        Ok((sync, differences))
+    }
+}
+
+/// Quotes `text` as a single argument for a POSIX shell.
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+/// The path of a file where the script is run, from the path Navidrome has.
+fn script_path(path: &str, config: &Config) -> String {
+    replace_folder(
+        path,
+        &config.tag_script_navidrome_folder,
+        &config.tag_script_music_folder,
+    )
+}
+
+/// A shell script setting the Apple Music values in each file with kid3-cli, so a rescan by Navidrome keeps them.
+pub fn tag_script(differences: &[TagDifference], config: &Config) -> String {
+    // writing to a string does not fail
+    let mut script = String::new();
+    let _ = writeln!(script, "#!/bin/sh");
+    let _ = writeln!(
+        script,
+        "# Sets tags to the values in Apple Music, using kid3-cli (https://kid3.kde.org)"
+    );
+    let _ = writeln!(script, "set -e");
+    for difference in differences {
+        let Some(path) = &difference.path else {
+            continue;
+        };
+        let Some(field) = TAG_FIELDS.iter().find(|f| f.name == difference.field) else {
+            continue;
+        };
+        // tags missing from Apple Music are left in the file
+        let Some(value) = &difference.apple else {
+            continue;
+        };
+        let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+        let command = format!("set \"{}\" \"{value}\"", field.frame);
+        let _ = writeln!(
+            script,
+            "kid3-cli -c {} {}",
+            shell_quote(&command),
+            shell_quote(&script_path(path, config))
+        );
+    }
+    script
+}
+
+/// Writes the differences as CSV, and the script to set the tags of the files.
+pub fn write_tag_differences(
+    differences: &[TagDifference],
+    config: &Config,
+) -> Result<(), csv::Error> {
+    let mut writer = csv::Writer::from_path(config.info_path(&config.tag_differences_file))?;
+    for difference in differences {
+        writer.serialize(difference)?;
+    }
+    writer.flush()?;
+    std::fs::write(
+        config.info_path(&config.tag_script_file),
+        tag_script(differences, config),
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::Connection;
+
+    use super::*;
+    use crate::test_fixtures::{library, track};
+
+    /// A database with a media file for each of tracks 1 and 2, with the genres Pop and Jazz.
+    fn writer() -> NavidromeWriter {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "CREATE TABLE media_file (id VARCHAR, path VARCHAR, title VARCHAR, album VARCHAR, artist VARCHAR,
+track_number INTEGER, disc_number INTEGER, genre VARCHAR, comment VARCHAR);
+INSERT INTO media_file VALUES ('m1', '/music/Band/First/1.mp3', 'Song 1', 'First', 'Band', 0, 0, 'Pop', '');
+INSERT INTO media_file VALUES ('m2', '/music/Band/First/2.mp3', 'Song 2', 'First', 'Band', 0, 0, 'Jazz', NULL);",
+        )
+        .unwrap();
+        NavidromeWriter { db }
+    }
+
+    fn genre(writer: &NavidromeWriter, id: &str) -> Option<String> {
+        writer
+            .db
+            .query_row(
+                "SELECT genre FROM media_file WHERE id = :id",
+                &[(":id", id)],
+                |row| row.get(0),
+            )
+            .unwrap()
+    }
+
+    fn tracks() -> Library {
+        library([
+            Track {
+                genre: Some("Rock".to_owned()),
+                comments: Some(" ".to_owned()),
+                ..track("1")
+            },
+            track("2"),
+            track("3"),
+        ])
+    }
+
+    #[test]
+    fn finds_differences() {
+        let writer = writer();
+        let (sync, differences) = writer.tag_differences(&tracks(), false).unwrap();
+        assert_eq!((sync.compared, sync.unmatched, sync.applied), (2, 1, 0));
+        let found: Vec<_> = differences
+            .iter()
+            .map(|d| {
+                (
+                    d.item_id.as_str(),
+                    d.field.as_str(),
+                    d.apple.as_deref(),
+                    d.navidrome.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("m1", "genre", Some("Rock"), Some("Pop")),
+                ("m2", "genre", None, Some("Jazz"))
+            ]
+        );
+        assert_eq!(genre(&writer, "m1").as_deref(), Some("Pop"));
+    }
+
+    #[test]
+    fn applies_only_values_apple_music_has() {
+        let writer = writer();
+        let (sync, differences) = writer.tag_differences(&tracks(), true).unwrap();
+        assert_eq!(sync.applied, 1);
+        assert_eq!(differences.len(), 2);
+        assert_eq!(genre(&writer, "m1").as_deref(), Some("Rock"));
+        assert_eq!(genre(&writer, "m2").as_deref(), Some("Jazz"));
+    }
+
+    fn difference(path: &str, apple: Option<&str>) -> TagDifference {
+        TagDifference {
+            path: Some(path.to_owned()),
+            field: "genre".to_owned(),
+            apple: apple.map(str::to_owned),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn script_quotes_values_and_paths() {
+        let script = tag_script(
+            &[difference("/music/It's/1.mp3", Some("Rock \"n\" Roll"))],
+            &Config::default(),
+        );
+        assert!(script.starts_with("#!/bin/sh\n"));
+        assert!(script.contains(
+            "kid3-cli -c 'set \"Genre\" \"Rock \\\"n\\\" Roll\"' '/music/It'\\''s/1.mp3'\n"
+        ));
+    }
+
+    #[test]
+    fn script_leaves_tags_missing_from_apple_music() {
+        let script = tag_script(
+            &[difference("/music/1.mp3", None), TagDifference::default()],
+            &Config::default(),
+        );
+        assert!(!script.contains("kid3-cli -c"));
+    }
+
+    #[test]
+    fn script_rewrites_paths() {
+        let config = Config {
+            tag_script_navidrome_folder: "/music/".to_owned(),
+            tag_script_music_folder: "/home/me/Music/".to_owned(),
+            ..Default::default()
+        };
+        let script = tag_script(
+            &[
+                difference("/music/Band/1.mp3", Some("Rock")),
+                difference("/other/2.mp3", Some("Rock")),
+            ],
+            &config,
+        );
+        assert!(script.contains("'/home/me/Music/Band/1.mp3'"));
+        assert!(script.contains("'/other/2.mp3'"));
+
+        let relative = Config {
+            tag_script_music_folder: "/home/me/Music/".to_owned(),
+            ..Default::default()
+        };
+        assert_eq!(
+            script_path("Band/1.mp3", &relative),
+            "/home/me/Music/Band/1.mp3"
+        );
+    }
+}
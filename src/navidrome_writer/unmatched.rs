@@ -0,0 +1,357 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use rusqlite::ToSql;
+use serde::{Deserialize, Serialize};
+
+use crate::structs::{track::Track, TrackID};
+
+use super::{NavidromeWriter, TrackMatcher};
+
+/// A track from Apple Music with no match in the navidrome database, with the closest rows which were found.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UnmatchedTrack {
+    pub id: TrackID,
+    pub persistent_id: String,
+    pub location: String,
+    pub kind: Option<String>,
+    pub query: BTreeMap<String, String>,
+    pub candidates: Vec<Candidate>,
+}
+
+/// A row of the media_file table, and how close it is to some track.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Candidate {
+    pub id: String,
+    pub path: String,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub title: Option<String>,
+    pub track_number: Option<usize>,
+    pub disc_number: Option<usize>,
+    pub similarity: f64,
+    pub mismatches: Vec<String>,
+}
+
+impl Candidate {
+    fn field(&self, field: &str) -> Option<String> {
+        match field {
+            "artist" => self.artist.clone(),
+            "album" => self.album.clone(),
+            "title" => self.title.clone(),
+            "track_number" => self.track_number.map(|n| n.to_string()),
+            "disc_number" => self.disc_number.map(|n| n.to_string()),
+            _ => None,
+        }
+    }
+}
+
+impl TrackMatcher<'_> {
+    /// The fields and values used to find a match for the track.
+    pub fn query_fields(&self) -> BTreeMap<String, String> {
+        let mut fields = BTreeMap::default();
+        for field in &self.selections {
+            let value = match *field {
+                "artist" => self.track.artist.clone(),
+                "album" => self.track.album_title.clone(),
+                "title" => self.track.title.clone(),
+                "track_number" => self.track.track_number.map(|n| n.to_string()),
+                "disc_number" => self.track.disc_number.map(|n| n.to_string()),
+                _ => None,
+            };
+            if let Some(value) = value {
+                fields.insert(field.to_string(), value);
+            }
+        }
+        fields
+    }
+}
+
+/// How similar `found` is to `wanted` for `field`, from 0 to 1, and whether the value would match.
+fn field_similarity(field: &str, wanted: &str, found: Option<&str>) -> (f64, bool) {
+    let Some(found) = found else {
+        return (0.0, false);
+    };
+    let wanted = wanted.to_lowercase();
+    let found = found.to_lowercase();
+    match field {
+        // titles are matched with a LIKE on the end of the title
+        "title" => (
+            strsim::normalized_levenshtein(&wanted, &found),
+            found.ends_with(&wanted),
+        ),
+        "track_number" | "disc_number" => {
+            let same = wanted == found;
+            (if same { 1.0 } else { 0.0 }, same)
+        }
+        _ => (
+            strsim::normalized_levenshtein(&wanted, &found),
+            wanted == found,
+        ),
+    }
+}
+
+/// Words common enough in names that searching for them would bring up much of the library.
+const STOP_WORDS: [&str; 24] = [
+    "the",
+    "and",
+    "for",
+    "from",
+    "with",
+    "you",
+    "your",
+    "are",
+    "not",
+    "but",
+    "this",
+    "that",
+    "feat",
+    "featuring",
+    "remix",
+    "mix",
+    "edit",
+    "version",
+    "live",
+    "remaster",
+    "remastered",
+    "mono",
+    "stereo",
+    "vol",
+];
+
+/// The words of `text` which narrow down the rows to compare, or the whole text if none do.
+///
+/// Words shorter than 3 characters and common words such as "the" and "feat" are left out.
+fn search_words(text: &str) -> Vec<String> {
+    let mut words: Vec<String> = vec![];
+    for word in text.split(|c: char| !c.is_alphanumeric()) {
+        if word.chars().count() >= 3
+            && !STOP_WORDS.iter().any(|w| w.eq_ignore_ascii_case(word))
+            && !words.iter().any(|w| w.eq_ignore_ascii_case(word))
+        {
+            words.push(word.to_owned());
+        }
+    }
+    let whole = text.trim().replace(['%', '_'], "");
+    if words.is_empty() && !whole.is_empty() {
+        words.push(whole);
+    }
+    words
+}
+
+impl NavidromeWriter {
+    /// Finds up to `count` rows of the media_file table closest to the track of `matcher`.
+    ///
+    /// Rows are considered if the artist, album, or title shares a word with that of the track, ignoring case, punctuation, and common words,
+    /// and ranked by how similar each field is.
+    pub fn nearest_candidates(
+        &self,
+        matcher: &TrackMatcher,
+        count: usize,
+    ) -> Result<Vec<Candidate>, rusqlite::Error> {
+        let query_fields = matcher.query_fields();
+
+        let mut patterns = vec![];
+        for (column, value) in [
+            ("artist", &matcher.track.artist),
+            ("album", &matcher.track.album_title),
+            ("title", &matcher.track.title),
+        ] {
+            if let Some(value) = value {
+                for word in search_words(value) {
+                    patterns.push((column, format!("%{word}%")));
+                }
+            }
+        }
+        if patterns.is_empty() {
+            return Ok(vec![]);
+        }
+        let names: Vec<String> = (0..patterns.len()).map(|i| format!(":word{i}")).collect();
+        let binds: Vec<String> = patterns
+            .iter()
+            .zip(&names)
+            .map(|((column, _), name)| format!("{column} LIKE {name}"))
+            .collect();
+        let parameters: Vec<(&str, &dyn ToSql)> = names
+            .iter()
+            .zip(&patterns)
+            .map(|(name, (_, pattern))| (name.as_str(), pattern as &dyn ToSql))
+            .collect();
+
+        let query_string = format!(
+            "SELECT id, path, artist, album, title, track_number, disc_number FROM media_file WHERE {}",
+            binds.join(" OR ")
+        );
+
+        let mut candidates = vec![];
+        let mut stmt = self.db.prepare(&query_string)?;
+        let mut rows = stmt.query(parameters.as_slice())?;
+        while let Some(row) = rows.next()? {
+            let mut candidate = Candidate {
+                id: row.get("id")?,
+                path: row.get::<_, Option<String>>("path")?.unwrap_or_default(),
+                artist: row.get("artist")?,
+                album: row.get("album")?,
+                title: row.get("title")?,
+                track_number: row.get("track_number")?,
+                disc_number: row.get("disc_number")?,
+                ..Default::default()
+            };
+
+            let mut total = 0.0;
+            for (field, wanted) in &query_fields {
+                let (similarity, matched) =
+                    field_similarity(field, wanted, candidate.field(field).as_deref());
+                total += similarity;
+                if !matched {
+                    candidate.mismatches.push(field.to_owned());
+                }
+            }
+            candidate.similarity = match query_fields.len() {
+                0 => 0.0,
+                fields => total / fields as f64,
+            };
+            candidates.push(candidate);
+        }
+
+        candidates.sort_by(|a, b| b.similarity.total_cmp(&a.similarity));
+        candidates.truncate(count);
+        Ok(candidates)
+    }
+
+    pub fn unmatched_track(
+        &self,
+        track: &Track,
+        count: usize,
+    ) -> Result<UnmatchedTrack, rusqlite::Error> {
+        let matcher = TrackMatcher::from_track(track);
+        Ok(UnmatchedTrack {
+            id: track.id.clone(),
+            persistent_id: track.persistent_id.clone(),
+            location: track.location.clone(),
+            kind: track.kind.clone(),
+            query: matcher.query_fields(),
+            candidates: self.nearest_candidates(&matcher, count)?,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+struct UnmatchedRow<'u> {
+    id: &'u str,
+    persistent_id: &'u str,
+    location: &'u str,
+    kind: Option<&'u str>,
+    artist: Option<&'u str>,
+    album: Option<&'u str>,
+    title: Option<&'u str>,
+    track_number: Option<&'u str>,
+    disc_number: Option<&'u str>,
+    candidate_rank: Option<usize>,
+    candidate_id: Option<&'u str>,
+    candidate_path: Option<&'u str>,
+    candidate_artist: Option<&'u str>,
+    candidate_album: Option<&'u str>,
+    candidate_title: Option<&'u str>,
+    candidate_track_number: Option<usize>,
+    candidate_disc_number: Option<usize>,
+    similarity: Option<f64>,
+    mismatches: String,
+}
+
+/// Writes unmatched tracks as CSV, with a row for each candidate, or a single row if there are no candidates.
+pub fn write_unmatched_csv(unmatched: &[UnmatchedTrack], path: &Path) -> Result<(), csv::Error> {
+    let mut writer = csv::Writer::from_path(path)?;
+    for track in unmatched {
+        let row = UnmatchedRow {
+            id: &track.id,
+            persistent_id: &track.persistent_id,
+            location: &track.location,
+            kind: track.kind.as_deref(),
+            artist: track.query.get("artist").map(|s| s.as_str()),
+            album: track.query.get("album").map(|s| s.as_str()),
+            title: track.query.get("title").map(|s| s.as_str()),
+            track_number: track.query.get("track_number").map(|s| s.as_str()),
+            disc_number: track.query.get("disc_number").map(|s| s.as_str()),
+            ..Default::default()
+        };
+        if track.candidates.is_empty() {
+            writer.serialize(&row)?;
+        }
+        for (rank, candidate) in track.candidates.iter().enumerate() {
+            writer.serialize(UnmatchedRow {
+                candidate_rank: Some(rank + 1),
+                candidate_id: Some(&candidate.id),
+                candidate_path: Some(&candidate.path),
+                candidate_artist: candidate.artist.as_deref(),
+                candidate_album: candidate.album.as_deref(),
+                candidate_title: candidate.title.as_deref(),
+                candidate_track_number: candidate.track_number,
+                candidate_disc_number: candidate.disc_number,
+                similarity: Some(candidate.similarity),
+                mismatches: candidate.mismatches.join(";"),
+                ..row.clone()
+            })?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::Connection;
+
+    use super::*;
+    use crate::test_fixtures::track;
+
+    #[test]
+    fn leaves_out_short_and_common_words() {
+        assert_eq!(
+            search_words("The Band feat. Guest & the Others"),
+            ["Band", "Guest", "Others"]
+        );
+        assert_eq!(search_words("Song (Live) - Remastered"), ["Song"]);
+        assert_eq!(search_words("band BAND"), ["band"]);
+        // with no other words, the whole text is searched for
+        assert_eq!(search_words("The The"), ["The The"]);
+        assert_eq!(search_words("U2"), ["U2"]);
+        assert!(search_words(" % ").is_empty());
+    }
+
+    #[test]
+    fn ranks_candidates() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "CREATE TABLE media_file (id VARCHAR, path VARCHAR, title VARCHAR, album VARCHAR, artist VARCHAR,
+track_number INTEGER, disc_number INTEGER);
+INSERT INTO media_file VALUES ('m1', 'Band/First/1.mp3', 'Song 1 (Live)', 'First', 'Band', NULL, NULL);
+INSERT INTO media_file VALUES ('m2', 'Band/Second/1.mp3', 'Song 1', 'Second', 'Band', NULL, NULL);
+INSERT INTO media_file VALUES ('m3', 'Other/Third/1.mp3', 'Another', 'Third', 'Other', NULL, NULL);
+INSERT INTO media_file VALUES ('m4', 'The Others/1.mp3', 'The End', 'Feat', 'The Others', NULL, NULL);",
+        )
+        .unwrap();
+        let writer = NavidromeWriter { db };
+        let track = Track {
+            title: Some("Song 1".to_owned()),
+            artist: Some("The Band".to_owned()),
+            ..track("1")
+        };
+        let candidates = writer
+            .nearest_candidates(&TrackMatcher::from_track(&track), 3)
+            .unwrap();
+        let ranked: Vec<_> = candidates
+            .iter()
+            .map(|c| (c.id.as_str(), c.mismatches.join(";")))
+            .collect();
+        // m3 shares no word with the track, and m4 only "the"
+        assert_eq!(
+            ranked,
+            [
+                ("m1", "artist;title".to_owned()),
+                ("m2", "album;artist".to_owned())
+            ]
+        );
+        assert!(candidates[0].similarity > candidates[1].similarity);
+    }
+}
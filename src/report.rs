@@ -0,0 +1,295 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::Path;
+use std::time::Instant;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    navidrome_writer::AnnotationWrite,
+    structs::{checks::Check, Library, TrackID},
+};
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Annotations {
+    pub inserted: usize,
+    pub changed: usize,
+    pub unchanged: usize,
+}
+
+impl Annotations {
+    pub fn record(&mut self, write: AnnotationWrite) {
+        match write {
+            AnnotationWrite::Inserted => self.inserted += 1,
+            AnnotationWrite::Changed => self.changed += 1,
+            AnnotationWrite::Unchanged => self.unchanged += 1,
+        }
+    }
+
+    pub fn written(&self) -> usize {
+        self.inserted + self.changed + self.unchanged
+    }
+}
+
+/// The outcome of matching and annotating tracks.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TrackSync {
+    pub matched: usize,
+    pub unmatched: usize,
+    pub ambiguous: usize,
+    /// Tracks unchanged since the last sync, which were not matched again.
+    pub skipped: usize,
+    /// Duplicate tracks merged into another track.
+    pub merged: usize,
+    /// Tracks whose skips were written to their own table.
+    pub skips_written: usize,
+    /// Unrated tracks given a low rating for being mostly skipped.
+    pub skip_rated: usize,
+    pub annotations: Annotations,
+}
+
+/// The outcome of writing the dates tracks were added as the dates media files and albums were created.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DateSync {
+    pub media_files: usize,
+    pub albums: usize,
+    pub unmatched: usize,
+}
+
+/// The outcome of comparing the tags of tracks with their media files.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TagSync {
+    pub compared: usize,
+    pub unmatched: usize,
+    pub differences: usize,
+    pub applied: usize,
+}
+
+/// The outcome of writing artist and album play counts.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CountSync {
+    pub artists: Annotations,
+    pub artists_missing: usize,
+    pub albums: Annotations,
+    pub albums_missing: usize,
+}
+
+/// The outcome of reconciling Apple Music and Navidrome.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Reconciliation {
+    pub tracks: TrackSync,
+    pub apple_changes: usize,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PlaylistExport {
+    pub name: String,
+    pub tracks: usize,
+    pub missing_tracks: Vec<TrackID>,
+    pub exported: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Stage {
+    pub name: String,
+    pub seconds: f64,
+}
+
+/// A summary of a run, built up as each stage completes.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RunReport {
+    pub library_date: DateTime<Utc>,
+    pub tracks: usize,
+    pub playlists: usize,
+    pub library_diagnostics: Vec<String>,
+    /// The number of warnings of each check, see [`Library::validate`].
+    pub library_warnings: BTreeMap<Check, usize>,
+    pub track_sync: Option<TrackSync>,
+    pub count_sync: Option<CountSync>,
+    pub date_sync: Option<DateSync>,
+    pub tag_sync: Option<TagSync>,
+    pub dry_run: bool,
+    pub reconciliation: Option<Reconciliation>,
+    pub playlist_exports: Option<Vec<PlaylistExport>>,
+    pub stages: Vec<Stage>,
+}
+
+impl RunReport {
+    pub fn record_library(&mut self, library: &Library) {
+        self.library_date = library.date;
+        self.tracks = library.tracks.len();
+        self.playlists = library.playlists.len();
+    }
+
+    /// Runs `stage`, recording how long it took under `name`.
+    pub fn time<T>(&mut self, name: &str, stage: impl FnOnce(&mut Self) -> T) -> T {
+        let start = Instant::now();
+        let result = stage(self);
+        self.stages.push(Stage {
+            name: name.to_owned(),
+            seconds: start.elapsed().as_secs_f64(),
+        });
+        result
+    }
+
+    pub fn json_export(&self, path: &Path) -> Result<(), std::io::Error> {
+        let report_json = serde_json::to_string_pretty(self)?;
+        let mut file = std::fs::File::create(path)?;
+        std::io::Write::write_all(&mut file, report_json.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn markdown_export(&self, path: &Path) -> Result<(), std::io::Error> {
+        let mut file = std::fs::File::create(path)?;
+        std::io::Write::write_all(&mut file, self.to_markdown().as_bytes())?;
+        Ok(())
+    }
+
+    pub fn to_markdown(&self) -> String {
+        // writing to a string does not fail
+        let mut md = String::new();
+        let _ = writeln!(md, "# Run report\n");
+        if self.dry_run {
+            let _ = writeln!(
+                md,
+                "A dry run, so nothing was written to the Navidrome database.\n"
+            );
+        }
+        let _ = writeln!(md, "## Library\n");
+        let _ = writeln!(md, "- Date: {}", self.library_date);
+        let _ = writeln!(md, "- Tracks: {}", self.tracks);
+        let _ = writeln!(md, "- Playlists: {}", self.playlists);
+        if !self.library_diagnostics.is_empty() {
+            let _ = writeln!(md, "\n### Skipped when reading\n");
+            for diagnostic in &self.library_diagnostics {
+                let _ = writeln!(md, "- {diagnostic}");
+            }
+        }
+
+        if !self.library_warnings.is_empty() {
+            let _ = writeln!(md, "\n### Warnings\n");
+            for (check, count) in &self.library_warnings {
+                let _ = writeln!(md, "- {check:?}: {count}");
+            }
+        }
+
+        if let Some(sync) = &self.track_sync {
+            let _ = writeln!(md, "\n## Tracks\n");
+            let _ = writeln!(md, "- Matched: {}", sync.matched);
+            let _ = writeln!(md, "- Unmatched: {}", sync.unmatched);
+            let _ = writeln!(md, "- Ambiguous: {}", sync.ambiguous);
+            let _ = writeln!(md, "- Unchanged since the last sync: {}", sync.skipped);
+            let _ = writeln!(md, "- Duplicates merged: {}", sync.merged);
+            let _ = writeln!(md, "- Skips written: {}", sync.skips_written);
+            let _ = writeln!(md, "- Rated low for skips: {}", sync.skip_rated);
+            let _ = writeln!(md);
+            let _ = writeln!(md, "| Annotations | Inserted | Changed | Unchanged |");
+            let _ = writeln!(md, "| --- | --- | --- | --- |");
+            annotation_row(&mut md, "Tracks", &sync.annotations);
+            if let Some(counts) = &self.count_sync {
+                annotation_row(&mut md, "Artists", &counts.artists);
+                annotation_row(&mut md, "Albums", &counts.albums);
+            }
+        }
+
+        if let Some(counts) = &self.count_sync {
+            let _ = writeln!(md, "\n## Artists and albums\n");
+            let _ = writeln!(md, "- Artists written: {}", counts.artists.written());
+            let _ = writeln!(md, "- Artists not found: {}", counts.artists_missing);
+            let _ = writeln!(md, "- Albums written: {}", counts.albums.written());
+            let _ = writeln!(md, "- Albums not found: {}", counts.albums_missing);
+        }
+
+        if let Some(dates) = &self.date_sync {
+            let _ = writeln!(md, "\n## Dates added\n");
+            let _ = writeln!(md, "- Media files changed: {}", dates.media_files);
+            let _ = writeln!(md, "- Albums changed: {}", dates.albums);
+            let _ = writeln!(md, "- Tracks not matched: {}", dates.unmatched);
+        }
+
+        if let Some(tags) = &self.tag_sync {
+            let _ = writeln!(md, "\n## Tags\n");
+            let _ = writeln!(md, "- Tracks compared: {}", tags.compared);
+            let _ = writeln!(md, "- Tracks not matched: {}", tags.unmatched);
+            let _ = writeln!(md, "- Differences: {}", tags.differences);
+            let _ = writeln!(md, "- Applied: {}", tags.applied);
+        }
+
+        if let Some(reconciliation) = &self.reconciliation {
+            let tracks = &reconciliation.tracks;
+            let _ = writeln!(md, "\n## Reconciliation\n");
+            let _ = writeln!(md, "- Matched: {}", tracks.matched);
+            let _ = writeln!(md, "- Unmatched: {}", tracks.unmatched);
+            let _ = writeln!(md, "- Ambiguous: {}", tracks.ambiguous);
+            let _ = writeln!(
+                md,
+                "- Changes for Apple Music: {}",
+                reconciliation.apple_changes
+            );
+            let _ = writeln!(md);
+            let _ = writeln!(md, "| Annotations | Inserted | Changed | Unchanged |");
+            let _ = writeln!(md, "| --- | --- | --- | --- |");
+            annotation_row(&mut md, "Tracks", &tracks.annotations);
+        }
+
+        if let Some(exports) = &self.playlist_exports {
+            let _ = writeln!(md, "\n## Playlists\n");
+            let _ = writeln!(md, "| Playlist | Tracks | Missing | Exported |");
+            let _ = writeln!(md, "| --- | --- | --- | --- |");
+            for export in exports {
+                let _ = writeln!(
+                    md,
+                    "| {} | {} | {} | {} |",
+                    cell(&export.name),
+                    export.tracks,
+                    cell(&export.missing_tracks.join(", ")),
+                    if export.exported { "yes" } else { "no" }
+                );
+            }
+        }
+
+        let _ = writeln!(md, "\n## Timings\n");
+        let _ = writeln!(md, "| Stage | Seconds |");
+        let _ = writeln!(md, "| --- | --- |");
+        for stage in &self.stages {
+            let _ = writeln!(md, "| {} | {:.3} |", stage.name, stage.seconds);
+        }
+
+        md
+    }
+}
+
+/// Text for a cell of a Markdown table, which would otherwise be split by a `|` or a line break.
+fn cell(text: &str) -> String {
+    text.replace('|', "\\|").replace(['\r', '\n'], " ")
+}
+
+fn annotation_row(md: &mut String, name: &str, annotations: &Annotations) {
+    let _ = writeln!(
+        md,
+        "| {name} | {} | {} | {} |",
+        annotations.inserted, annotations.changed, annotations.unchanged
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_playlist_names() {
+        let report = RunReport {
+            playlist_exports: Some(vec![PlaylistExport {
+                name: "This | That".to_owned(),
+                tracks: 2,
+                missing_tracks: vec![],
+                exported: false,
+            }]),
+            ..Default::default()
+        };
+        assert!(report
+            .to_markdown()
+            .contains("| This \\| That | 2 |  | no |"));
+    }
+}
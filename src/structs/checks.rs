@@ -0,0 +1,137 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::{track::Track, Library, TrackID};
+
+/// The kinds of suspicious data looked for by [`Library::validate`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Check {
+    EmptyTitle,
+    /// The location is a file which does not exist.
+    MissingFile,
+    PlayedBeforeAdded,
+    TrackNumberOverTotal,
+    DiscNumberZero,
+    /// A playlist has a track id which is not in the library.
+    MissingPlaylistTrack,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LibraryWarning {
+    pub check: Check,
+    pub track_id: Option<TrackID>,
+    pub playlist: Option<String>,
+    pub message: String,
+}
+
+fn describe(track: &Track) -> String {
+    format!(
+        "{} - {} ({})",
+        track.artist.as_deref().unwrap_or("[No artist]"),
+        track.title.as_deref().unwrap_or("[No title]"),
+        track.id
+    )
+}
+
+fn check_track(track: &Track, check_files: bool, warnings: &mut Vec<LibraryWarning>) {
+    let mut warn = |check, message: String| {
+        warnings.push(LibraryWarning {
+            check,
+            track_id: Some(track.id.clone()),
+            playlist: None,
+            message,
+        })
+    };
+
+    if track
+        .title
+        .as_deref()
+        .is_none_or(|title| title.trim().is_empty())
+    {
+        warn(
+            Check::EmptyTitle,
+            format!("{} has no title", describe(track)),
+        );
+    }
+    if check_files {
+        if let Some(path) = track.file_path() {
+            if !path.exists() {
+                warn(
+                    Check::MissingFile,
+                    format!(
+                        "{} is at {}, which does not exist",
+                        describe(track),
+                        path.display()
+                    ),
+                );
+            }
+        }
+    }
+    if let Some(played) = track.play_date {
+        if played < track.date_added {
+            warn(
+                Check::PlayedBeforeAdded,
+                format!(
+                    "{} was last played {played}, before it was added {}",
+                    describe(track),
+                    track.date_added
+                ),
+            );
+        }
+    }
+    if let (Some(number), Some(total)) = (track.track_number, track.total_tracks) {
+        if number > total {
+            warn(
+                Check::TrackNumberOverTotal,
+                format!("{} is track {number} of {total}", describe(track)),
+            );
+        }
+    }
+    if track.disc_number == Some(0) {
+        warn(
+            Check::DiscNumberZero,
+            format!("{} is on disc 0", describe(track)),
+        );
+    }
+}
+
+impl Library {
+    /// Looks for suspicious data in the tracks and playlists, with `check_files` to also check each file exists.
+    pub fn validate(&self, check_files: bool) -> Vec<LibraryWarning> {
+        let mut warnings = vec![];
+        let mut tracks: Vec<&Track> = self.tracks.values().collect();
+        tracks.sort_by(|a, b| a.id.cmp(&b.id));
+        for track in tracks {
+            check_track(track, check_files, &mut warnings);
+        }
+
+        for playlist in &self.playlists {
+            for id in &playlist.track_ids {
+                if !self.tracks.contains_key(id) {
+                    warnings.push(LibraryWarning {
+                        check: Check::MissingPlaylistTrack,
+                        track_id: Some(id.clone()),
+                        playlist: Some(playlist.name.clone()),
+                        message: format!(
+                            "Playlist {} has track {id}, which is not in the library",
+                            playlist.name
+                        ),
+                    });
+                }
+            }
+        }
+        warnings.sort_by_key(|warning| warning.check);
+        warnings
+    }
+}
+
+/// The number of warnings of each check.
+pub fn count_warnings(warnings: &[LibraryWarning]) -> BTreeMap<Check, usize> {
+    let mut counts = BTreeMap::default();
+    for warning in warnings {
+        *counts.entry(warning.check).or_default() += 1;
+    }
+    counts
+}
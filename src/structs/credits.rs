@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+
+use super::Library;
+
+/// Separators between artists in a credit, matched without regard to case.
+///
+/// Words are matched with the surrounding spaces, so names which merely contain them are not split.
+const SEPARATORS: [&str; 9] = [
+    ";",
+    ",",
+    "&",
+    " feat. ",
+    " feat ",
+    " featuring ",
+    " ft. ",
+    " ft ",
+    " with ",
+];
+
+/// Splits artist credits such as "Band feat. Guest" into the artists credited.
+#[derive(Debug, Default, Clone)]
+pub struct ArtistCredits {
+    /// Names which contain a separator but are a single artist, such as "Simon & Garfunkel".
+    pub allow_list: Vec<String>,
+}
+
+impl ArtistCredits {
+    pub fn new(allow_list: &[String]) -> Self {
+        let mut allow_list = allow_list.to_vec();
+        // longer names first, so a name containing another is kept whole
+        allow_list.sort_by_key(|name| std::cmp::Reverse(name.len()));
+        ArtistCredits { allow_list }
+    }
+
+    /// The artists credited, in order of appearance and without repeats.
+    pub fn parse(&self, credit: &str) -> Vec<String> {
+        let mut artists: Vec<String> = vec![];
+        let mut rest = credit.to_owned();
+
+        // allowed names are taken out before splitting, and kept in the place they appear
+        let mut allowed: Vec<(usize, String)> = vec![];
+        for name in &self.allow_list {
+            while let Some(start) = rest.find(name.as_str()) {
+                allowed.push((start, name.clone()));
+                rest.replace_range(start..start + name.len(), &";".repeat(name.len()));
+            }
+        }
+
+        // brackets, as in "Band (feat. Guest)", are treated as spaces
+        let rest = rest.replace(['(', ')', '[', ']'], " ");
+        let lower = rest.to_ascii_lowercase();
+        let mut found: Vec<(usize, String)> = allowed;
+        let mut start = 0;
+        while start < rest.len() {
+            let next = SEPARATORS
+                .iter()
+                .filter_map(|separator| {
+                    lower[start..]
+                        .find(separator)
+                        .map(|at| (start + at, separator.len()))
+                })
+                .min();
+            let end = next.map(|(at, _)| at).unwrap_or(rest.len());
+            let part = rest[start..end].trim();
+            if !part.is_empty() {
+                found.push((start, part.to_owned()));
+            }
+            start = match next {
+                Some((at, length)) => at + length,
+                None => rest.len(),
+            };
+        }
+
+        found.sort_by_key(|(position, _)| *position);
+        for (_, artist) in found {
+            if !artists.contains(&artist) {
+                artists.push(artist);
+            }
+        }
+        artists
+    }
+}
+
+impl Library {
+    /// The play count of each artist credited as an artist of a track.
+    ///
+    /// A track with several artists counts towards each of them.
+    /// Album artists are left out, as their plays are counted by album, and "Various Artists" would gather every compilation.
+    pub fn credited_artist_playcounts(&self, credits: &ArtistCredits) -> HashMap<String, usize> {
+        let mut counts: HashMap<String, usize> = HashMap::default();
+        for track in self.tracks.values() {
+            let Some(credit) = &track.artist else {
+                continue;
+            };
+            for artist in credits.parse(credit) {
+                *counts.entry(artist).or_default() += track.play_count;
+            }
+        }
+        counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::track::Track;
+    use crate::test_fixtures::{library, track};
+
+    fn parse(credit: &str) -> Vec<String> {
+        ArtistCredits::default().parse(credit)
+    }
+
+    #[test]
+    fn splits_on_each_separator() {
+        for credit in [
+            "Band; Guest",
+            "Band, Guest",
+            "Band & Guest",
+            "Band feat. Guest",
+            "Band feat Guest",
+            "Band featuring Guest",
+            "Band ft. Guest",
+            "Band ft Guest",
+            "Band with Guest",
+            "Band FEAT. Guest",
+            "Band (feat. Guest)",
+        ] {
+            assert_eq!(parse(credit), ["Band", "Guest"], "{credit}");
+        }
+    }
+
+    #[test]
+    fn keeps_names_containing_separator_words() {
+        assert_eq!(parse("Withered Hand"), ["Withered Hand"]);
+        assert_eq!(parse("Daft Punk"), ["Daft Punk"]);
+    }
+
+    #[test]
+    fn drops_repeats_and_empty_parts() {
+        assert_eq!(parse("Band, Guest & Band"), ["Band", "Guest"]);
+        assert_eq!(parse("Band, , Guest"), ["Band", "Guest"]);
+        assert!(parse("").is_empty());
+    }
+
+    #[test]
+    fn keeps_allowed_names_whole() {
+        let credits = ArtistCredits::new(&[
+            "Simon & Garfunkel".to_owned(),
+            "Earth, Wind & Fire".to_owned(),
+        ]);
+        assert_eq!(credits.parse("Simon & Garfunkel"), ["Simon & Garfunkel"]);
+        assert_eq!(
+            credits.parse("Guest feat. Earth, Wind & Fire"),
+            ["Guest", "Earth, Wind & Fire"]
+        );
+        assert_eq!(
+            credits.parse("Simon & Garfunkel & Guest"),
+            ["Simon & Garfunkel", "Guest"]
+        );
+    }
+
+    #[test]
+    fn credits_track_artists_only() {
+        let library = library([
+            Track {
+                artist: Some("Band feat. Guest".to_owned()),
+                album_artist: Some("Band".to_owned()),
+                play_count: 3,
+                ..track("1")
+            },
+            Track {
+                artist: Some("Solo".to_owned()),
+                album_artist: Some("Various Artists".to_owned()),
+                play_count: 2,
+                ..track("2")
+            },
+        ]);
+        let counts = library.credited_artist_playcounts(&ArtistCredits::default());
+        assert_eq!(counts.get("Band"), Some(&3));
+        assert_eq!(counts.get("Guest"), Some(&3));
+        assert_eq!(counts.get("Solo"), Some(&2));
+        assert_eq!(counts.get("Various Artists"), None);
+    }
+}
@@ -0,0 +1,446 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write as _;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{track::Track, Library};
+
+/// Enough of a track to recognise it.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TrackSummary {
+    pub persistent_id: String,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album_title: Option<String>,
+}
+
+impl TrackSummary {
+    fn from_track(track: &Track) -> Self {
+        TrackSummary {
+            persistent_id: track.persistent_id.clone(),
+            title: track.title.clone(),
+            artist: track.artist.clone(),
+            album_title: track.album_title.clone(),
+        }
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "{} - {}",
+            self.artist.as_deref().unwrap_or("[No artist]"),
+            self.title.as_deref().unwrap_or("[No title]")
+        )
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ValueChange<T> {
+    pub track: TrackSummary,
+    pub before: T,
+    pub after: T,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MetadataEdit {
+    pub track: TrackSummary,
+    pub field: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PlaylistStatus {
+    Added,
+    Removed,
+    Changed,
+}
+
+/// Changes to a playlist, with tracks given by persistent id.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlaylistChange {
+    pub persistent_id: String,
+    pub name: String,
+    pub status: PlaylistStatus,
+    pub tracks_added: Vec<String>,
+    pub tracks_removed: Vec<String>,
+}
+
+/// The differences between two versions of a library, with tracks and playlists matched by persistent id.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LibraryDiff {
+    pub from_date: DateTime<Utc>,
+    pub to_date: DateTime<Utc>,
+    pub added: Vec<TrackSummary>,
+    pub removed: Vec<TrackSummary>,
+    pub play_counts: Vec<ValueChange<usize>>,
+    pub ratings: Vec<ValueChange<usize>>,
+    pub newly_loved: Vec<TrackSummary>,
+    pub edits: Vec<MetadataEdit>,
+    pub playlists: Vec<PlaylistChange>,
+}
+
+type Field = (&'static str, fn(&Track) -> Option<String>);
+
+/// Fields compared for metadata edits.
+const EDIT_FIELDS: [Field; 13] = [
+    ("title", |t| t.title.clone()),
+    ("artist", |t| t.artist.clone()),
+    ("album_artist", |t| t.album_artist.clone()),
+    ("album_title", |t| t.album_title.clone()),
+    ("composer", |t| t.composer.clone()),
+    ("genre", |t| t.genre.clone()),
+    ("grouping", |t| t.grouping.clone()),
+    ("comments", |t| t.comments.clone()),
+    ("work", |t| t.work.clone()),
+    ("year", |t| t.year.map(|y| y.to_string())),
+    ("track_number", |t| t.track_number.map(|n| n.to_string())),
+    ("disc_number", |t| t.disc_number.map(|n| n.to_string())),
+    ("location", |t| Some(t.location.clone())),
+];
+
+impl Library {
+    fn tracks_by_persistent_id(&self) -> HashMap<&str, &Track> {
+        self.tracks
+            .values()
+            .map(|track| (track.persistent_id.as_str(), track))
+            .collect()
+    }
+
+    /// The persistent ids of the tracks of each playlist, keyed by the persistent id of the playlist.
+    fn playlist_members(&self) -> HashMap<&str, (&str, BTreeSet<&str>)> {
+        self.playlists
+            .iter()
+            .map(|playlist| {
+                let members = playlist
+                    .track_ids
+                    .iter()
+                    .filter_map(|id| self.tracks.get(id))
+                    .map(|track| track.persistent_id.as_str())
+                    .collect();
+                (
+                    playlist.persistent_id.as_str(),
+                    (playlist.name.as_str(), members),
+                )
+            })
+            .collect()
+    }
+
+    /// The changes from `self` to `newer`.
+    pub fn diff(&self, newer: &Library) -> LibraryDiff {
+        let mut diff = LibraryDiff {
+            from_date: self.date,
+            to_date: newer.date,
+            ..Default::default()
+        };
+
+        let old_tracks = self.tracks_by_persistent_id();
+        let new_tracks = newer.tracks_by_persistent_id();
+
+        for (id, old) in &old_tracks {
+            if !new_tracks.contains_key(id) {
+                diff.removed.push(TrackSummary::from_track(old));
+            }
+        }
+
+        for (id, new) in &new_tracks {
+            let Some(old) = old_tracks.get(id) else {
+                diff.added.push(TrackSummary::from_track(new));
+                continue;
+            };
+
+            if old.play_count != new.play_count {
+                diff.play_counts.push(ValueChange {
+                    track: TrackSummary::from_track(new),
+                    before: old.play_count,
+                    after: new.play_count,
+                });
+            }
+
+            if old.rating != new.rating {
+                diff.ratings.push(ValueChange {
+                    track: TrackSummary::from_track(new),
+                    before: old.rating,
+                    after: new.rating,
+                });
+            }
+
+            if (new.loved || new.favourited) && !(old.loved || old.favourited) {
+                diff.newly_loved.push(TrackSummary::from_track(new));
+            }
+
+            for (field, value) in EDIT_FIELDS {
+                let (before, after) = (value(old), value(new));
+                if before != after {
+                    diff.edits.push(MetadataEdit {
+                        track: TrackSummary::from_track(new),
+                        field: field.to_owned(),
+                        before,
+                        after,
+                    });
+                }
+            }
+        }
+
+        let old_playlists = self.playlist_members();
+        let new_playlists = newer.playlist_members();
+
+        for (id, (name, members)) in &old_playlists {
+            if !new_playlists.contains_key(id) {
+                diff.playlists.push(PlaylistChange {
+                    persistent_id: id.to_string(),
+                    name: name.to_string(),
+                    status: PlaylistStatus::Removed,
+                    tracks_added: vec![],
+                    tracks_removed: members.iter().map(|m| m.to_string()).collect(),
+                });
+            }
+        }
+
+        for (id, (name, members)) in &new_playlists {
+            let (status, old_members) = match old_playlists.get(id) {
+                Some((_, old_members)) => (PlaylistStatus::Changed, old_members.clone()),
+                None => (PlaylistStatus::Added, BTreeSet::default()),
+            };
+            let tracks_added: Vec<String> = members
+                .difference(&old_members)
+                .map(|m| m.to_string())
+                .collect();
+            let tracks_removed: Vec<String> = old_members
+                .difference(members)
+                .map(|m| m.to_string())
+                .collect();
+            if status == PlaylistStatus::Added
+                || !tracks_added.is_empty()
+                || !tracks_removed.is_empty()
+            {
+                diff.playlists.push(PlaylistChange {
+                    persistent_id: id.to_string(),
+                    name: name.to_string(),
+                    status,
+                    tracks_added,
+                    tracks_removed,
+                });
+            }
+        }
+
+        diff.sort();
+        diff
+    }
+}
+
+impl LibraryDiff {
+    fn sort(&mut self) {
+        let key = |t: &TrackSummary| (t.artist.clone(), t.title.clone(), t.persistent_id.clone());
+        self.added.sort_by_key(key);
+        self.removed.sort_by_key(key);
+        self.newly_loved.sort_by_key(key);
+        // the most listened to first
+        self.play_counts.sort_by(|a, b| {
+            let delta = |c: &ValueChange<usize>| c.after as isize - c.before as isize;
+            delta(b)
+                .cmp(&delta(a))
+                .then_with(|| key(&a.track).cmp(&key(&b.track)))
+        });
+        self.ratings.sort_by_key(|c| key(&c.track));
+        self.edits.sort_by_key(|e| (key(&e.track), e.field.clone()));
+        self.playlists
+            .sort_by(|a, b| (&a.name, &a.persistent_id).cmp(&(&b.name, &b.persistent_id)));
+    }
+
+    /// Total plays added, ignoring any play counts which went down.
+    pub fn plays(&self) -> usize {
+        self.play_counts
+            .iter()
+            .map(|c| c.after.saturating_sub(c.before))
+            .sum()
+    }
+
+    pub fn json_export(&self, path: &std::path::Path) -> Result<(), std::io::Error> {
+        let diff_json = serde_json::to_string_pretty(self)?;
+        let mut file = std::fs::File::create(path)?;
+        std::io::Write::write_all(&mut file, diff_json.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn to_text(&self) -> String {
+        // writing to a string does not fail
+        let mut text = String::new();
+        let _ = writeln!(text, "Changes from {} to {}", self.from_date, self.to_date);
+
+        let _ = writeln!(
+            text,
+            "\nPlays: {} across {} tracks",
+            self.plays(),
+            self.play_counts.len()
+        );
+        for change in &self.play_counts {
+            let _ = writeln!(
+                text,
+                "  {:+} {} ({} -> {})",
+                change.after as isize - change.before as isize,
+                change.track.describe(),
+                change.before,
+                change.after
+            );
+        }
+
+        let _ = writeln!(text, "\nAdded: {}", self.added.len());
+        for track in &self.added {
+            let _ = writeln!(text, "  {}", track.describe());
+        }
+
+        let _ = writeln!(text, "\nRemoved: {}", self.removed.len());
+        for track in &self.removed {
+            let _ = writeln!(text, "  {}", track.describe());
+        }
+
+        let _ = writeln!(text, "\nNewly loved: {}", self.newly_loved.len());
+        for track in &self.newly_loved {
+            let _ = writeln!(text, "  {}", track.describe());
+        }
+
+        let _ = writeln!(text, "\nRatings changed: {}", self.ratings.len());
+        for change in &self.ratings {
+            let _ = writeln!(
+                text,
+                "  {} ({} -> {})",
+                change.track.describe(),
+                change.before,
+                change.after
+            );
+        }
+
+        let _ = writeln!(text, "\nMetadata edits: {}", self.edits.len());
+        for edit in &self.edits {
+            let _ = writeln!(
+                text,
+                "  {} {}: {:?} -> {:?}",
+                edit.track.describe(),
+                edit.field,
+                edit.before,
+                edit.after
+            );
+        }
+
+        let _ = writeln!(text, "\nPlaylists changed: {}", self.playlists.len());
+        for playlist in &self.playlists {
+            let _ = writeln!(
+                text,
+                "  {} ({:?}): {} added, {} removed",
+                playlist.name,
+                playlist.status,
+                playlist.tracks_added.len(),
+                playlist.tracks_removed.len()
+            );
+        }
+
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::playlist::Playlist;
+    use crate::test_fixtures::{date, library, track};
+
+    fn playlist(track_ids: &[&str]) -> Playlist {
+        Playlist {
+            name: "Mix".to_owned(),
+            persistent_id: "PL".to_owned(),
+            track_ids: track_ids.iter().map(|id| id.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn older() -> Library {
+        Library {
+            date: date("2024-01-01T00:00:00Z"),
+            playlists: vec![playlist(&["1", "2"])],
+            ..library([
+                Track {
+                    play_count: 2,
+                    ..track("1")
+                },
+                track("2"),
+                track("3"),
+            ])
+        }
+    }
+
+    fn newer() -> Library {
+        // track ids may change between exports, so tracks are matched by persistent id
+        let renumbered = |id: &str, old: &str| Track {
+            id: id.to_owned(),
+            ..track(old)
+        };
+        Library {
+            date: date("2024-02-01T00:00:00Z"),
+            playlists: vec![playlist(&["11", "4"])],
+            ..library([
+                Track {
+                    play_count: 5,
+                    rating: 80,
+                    loved: true,
+                    genre: Some("Rock".to_owned()),
+                    ..renumbered("11", "1")
+                },
+                renumbered("12", "2"),
+                track("4"),
+            ])
+        }
+    }
+
+    fn ids(tracks: &[TrackSummary]) -> Vec<&str> {
+        tracks.iter().map(|t| t.persistent_id.as_str()).collect()
+    }
+
+    #[test]
+    fn added_and_removed_tracks() {
+        let diff = older().diff(&newer());
+        assert_eq!(ids(&diff.added), ["P4"]);
+        assert_eq!(ids(&diff.removed), ["P3"]);
+        assert_eq!(diff.from_date, date("2024-01-01T00:00:00Z"));
+        assert_eq!(diff.to_date, date("2024-02-01T00:00:00Z"));
+    }
+
+    #[test]
+    fn changed_tracks() {
+        let diff = older().diff(&newer());
+        let changes = |changes: &[ValueChange<usize>]| -> Vec<(String, usize, usize)> {
+            changes
+                .iter()
+                .map(|c| (c.track.persistent_id.clone(), c.before, c.after))
+                .collect()
+        };
+        assert_eq!(changes(&diff.play_counts), [("P1".to_owned(), 2, 5)]);
+        assert_eq!(diff.plays(), 3);
+        assert_eq!(changes(&diff.ratings), [("P1".to_owned(), 0, 80)]);
+        assert_eq!(ids(&diff.newly_loved), ["P1"]);
+        let edits: Vec<_> = diff
+            .edits
+            .iter()
+            .map(|e| (e.field.as_str(), e.before.as_deref(), e.after.as_deref()))
+            .collect();
+        assert_eq!(edits, [("genre", None, Some("Rock"))]);
+    }
+
+    #[test]
+    fn playlist_changes() {
+        let diff = older().diff(&newer());
+        assert_eq!(diff.playlists.len(), 1);
+        let playlist = &diff.playlists[0];
+        assert_eq!(playlist.status, PlaylistStatus::Changed);
+        assert_eq!(playlist.tracks_added, ["P4"]);
+        assert_eq!(playlist.tracks_removed, ["P2"]);
+    }
+
+    #[test]
+    fn no_changes() {
+        let diff = older().diff(&older());
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+        assert!(diff.play_counts.is_empty() && diff.edits.is_empty());
+        assert!(diff.playlists.is_empty());
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::{track::Track, Library, TrackID};
+
+/// Why tracks were taken to be duplicates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DuplicateReason {
+    /// The same size and kind, so likely the same file imported twice.
+    Identical,
+    /// Kinds differ, so likely the same song in different formats.
+    DifferentFormats,
+    /// The same metadata and a similar duration.
+    SameMetadata,
+}
+
+/// Tracks which appear to be the same song, with the one suggested to keep.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DuplicateCluster {
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub title: Option<String>,
+    pub reason: DuplicateReason,
+    pub canonical: TrackID,
+    pub duplicates: Vec<TrackID>,
+}
+
+/// Text compared without regard to case, punctuation, or spacing.
+fn normalize(text: &Option<String>) -> String {
+    text.as_deref()
+        .unwrap_or_default()
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Track and disc numbers agree, where both tracks have them.
+fn same_position(a: &Track, b: &Track) -> bool {
+    let agree = |a: Option<usize>, b: Option<usize>| a.is_none() || b.is_none() || a == b;
+    agree(a.track_number, b.track_number) && agree(a.disc_number, b.disc_number)
+}
+
+/// The most played track, then the one with the highest bit rate, the largest, and the first added.
+fn canonical<'t>(tracks: &[&'t Track]) -> &'t Track {
+    tracks
+        .iter()
+        .copied()
+        .min_by(|a, b| {
+            b.play_count
+                .cmp(&a.play_count)
+                .then(b.bit_rate.cmp(&a.bit_rate))
+                .then(b.size.cmp(&a.size))
+                .then(a.date_added.cmp(&b.date_added))
+                .then(a.id.cmp(&b.id))
+        })
+        .unwrap_or(tracks[0])
+}
+
+fn reason(tracks: &[&Track]) -> DuplicateReason {
+    let first = tracks[0];
+    if tracks
+        .iter()
+        .all(|t| t.size == first.size && t.kind == first.kind)
+    {
+        DuplicateReason::Identical
+    } else if tracks.iter().any(|t| t.kind != first.kind) {
+        DuplicateReason::DifferentFormats
+    } else {
+        DuplicateReason::SameMetadata
+    }
+}
+
+impl Library {
+    /// Clusters of tracks with the same artist, album, and title, ignoring case and punctuation, whose durations are within `tolerance` of each other.
+    pub fn duplicates(&self, tolerance: Duration) -> Vec<DuplicateCluster> {
+        let mut songs: HashMap<(String, String, String), Vec<&Track>> = HashMap::default();
+        for track in self.tracks.values() {
+            if track.title.is_none() {
+                continue;
+            }
+            let key = (
+                normalize(&track.artist),
+                normalize(&track.album_title),
+                normalize(&track.title),
+            );
+            songs.entry(key).or_default().push(track);
+        }
+
+        let mut clusters = vec![];
+        for (_, mut tracks) in songs {
+            if tracks.len() < 2 {
+                continue;
+            }
+            tracks.sort_by(|a, b| (a.duration, &a.id).cmp(&(b.duration, &b.id)));
+            let mut groups: Vec<Vec<&Track>> = vec![];
+            for track in tracks {
+                let group = groups.iter_mut().find(|group| {
+                    track.duration.abs_diff(group[0].duration) <= tolerance
+                        && group.iter().all(|other| same_position(track, other))
+                });
+                match group {
+                    Some(group) => group.push(track),
+                    None => groups.push(vec![track]),
+                }
+            }
+
+            for group in groups.into_iter().filter(|group| group.len() > 1) {
+                let keep = canonical(&group);
+                let mut duplicates: Vec<TrackID> = group
+                    .iter()
+                    .filter(|t| t.id != keep.id)
+                    .map(|t| t.id.clone())
+                    .collect();
+                duplicates.sort();
+                clusters.push(DuplicateCluster {
+                    artist: keep.artist.clone(),
+                    album: keep.album_title.clone(),
+                    title: keep.title.clone(),
+                    reason: reason(&group),
+                    canonical: keep.id.clone(),
+                    duplicates,
+                });
+            }
+        }
+        clusters.sort_by(|a, b| {
+            (&a.artist, &a.album, &a.title, &a.canonical).cmp(&(
+                &b.artist,
+                &b.album,
+                &b.title,
+                &b.canonical,
+            ))
+        });
+        clusters
+    }
+
+    /// The canonical track of each cluster, with the plays, skips, rating, and love of its duplicates, and the ids of the duplicates merged into them.
+    ///
+    /// Play and skip counts are summed, the latest dates kept, and the highest rating kept.
+    pub fn merge_duplicates(
+        &self,
+        clusters: &[DuplicateCluster],
+    ) -> (HashMap<TrackID, Track>, HashSet<TrackID>) {
+        let mut merged = HashMap::default();
+        let mut merged_away = HashSet::default();
+        for cluster in clusters {
+            let Some(canonical) = self.tracks.get(&cluster.canonical) else {
+                continue;
+            };
+            let mut track = canonical.clone();
+            for id in &cluster.duplicates {
+                let Some(duplicate) = self.tracks.get(id) else {
+                    continue;
+                };
+                track.play_count += duplicate.play_count;
+                track.skip_count += duplicate.skip_count;
+                track.play_date = track.play_date.max(duplicate.play_date);
+                track.skip_date = track.skip_date.max(duplicate.skip_date);
+                track.rating = track.rating.max(duplicate.rating);
+                track.loved |= duplicate.loved;
+                track.favourited |= duplicate.favourited;
+                merged_away.insert(id.clone());
+            }
+            merged.insert(cluster.canonical.clone(), track);
+        }
+        (merged, merged_away)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{self, library};
+
+    fn track(id: &str, title: &str, seconds: u64, kind: &str, play_count: usize) -> Track {
+        Track {
+            title: Some(title.to_owned()),
+            duration: Duration::from_secs(seconds),
+            kind: Some(kind.to_owned()),
+            play_count,
+            ..test_fixtures::track(id)
+        }
+    }
+
+    const TOLERANCE: Duration = Duration::from_secs(2);
+
+    #[test]
+    fn clusters_within_the_tolerance() {
+        let library = library(vec![
+            track("1", "Song One", 200, "MPEG audio file", 3),
+            track("2", "song one!", 201, "MPEG audio file", 5),
+            track("3", "Song One", 230, "MPEG audio file", 1),
+            track("4", "Song Two", 200, "MPEG audio file", 1),
+        ]);
+        let clusters = library.duplicates(TOLERANCE);
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].canonical, "2");
+        assert_eq!(clusters[0].duplicates, ["1"]);
+        assert_eq!(clusters[0].reason, DuplicateReason::Identical);
+    }
+
+    #[test]
+    fn tolerance_is_inclusive() {
+        let library = library(vec![
+            track("1", "Song One", 200, "MPEG audio file", 0),
+            track("2", "Song One", 202, "MPEG audio file", 0),
+        ]);
+        assert_eq!(library.duplicates(TOLERANCE).len(), 1);
+        assert!(library.duplicates(Duration::from_secs(1)).is_empty());
+    }
+
+    #[test]
+    fn different_positions_are_not_duplicates() {
+        let library = library(vec![
+            Track {
+                track_number: Some(1),
+                ..track("1", "Intro", 60, "MPEG audio file", 0)
+            },
+            Track {
+                track_number: Some(7),
+                ..track("2", "Intro", 60, "MPEG audio file", 0)
+            },
+            track("3", "Intro", 60, "MPEG audio file", 0),
+        ]);
+        let clusters = library.duplicates(TOLERANCE);
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].canonical, "1");
+        assert_eq!(clusters[0].duplicates, ["3"]);
+    }
+
+    #[test]
+    fn mixed_formats() {
+        let library = library(vec![
+            Track {
+                bit_rate: Some(256),
+                ..track("1", "Song One", 200, "MPEG audio file", 2)
+            },
+            Track {
+                bit_rate: Some(1000),
+                size: 30000,
+                ..track("2", "Song One", 200, "Apple Lossless audio file", 2)
+            },
+            Track {
+                size: 2000,
+                ..track("3", "Song One", 201, "MPEG audio file", 2)
+            },
+        ]);
+        let clusters = library.duplicates(TOLERANCE);
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].reason, DuplicateReason::DifferentFormats);
+        // the play counts are equal, so the highest bit rate is kept
+        assert_eq!(clusters[0].canonical, "2");
+        assert_eq!(clusters[0].duplicates, ["1", "3"]);
+    }
+
+    #[test]
+    fn same_metadata() {
+        let library = library(vec![
+            track("1", "Song One", 200, "MPEG audio file", 0),
+            Track {
+                size: 2000,
+                ..track("2", "Song One", 200, "MPEG audio file", 0)
+            },
+        ]);
+        assert_eq!(
+            library.duplicates(TOLERANCE)[0].reason,
+            DuplicateReason::SameMetadata
+        );
+    }
+
+    #[test]
+    fn merges_into_the_canonical_track() {
+        let date = |day| chrono::DateTime::parse_from_rfc3339(day).unwrap().to_utc();
+        let library = library(vec![
+            Track {
+                play_date: Some(date("2024-01-01T00:00:00Z")),
+                rating: 60,
+                ..track("1", "Song One", 200, "MPEG audio file", 10)
+            },
+            Track {
+                play_date: Some(date("2024-06-01T00:00:00Z")),
+                skip_count: 2,
+                rating: 80,
+                loved: true,
+                ..track("2", "Song One", 201, "Apple Lossless audio file", 4)
+            },
+        ]);
+        let clusters = library.duplicates(TOLERANCE);
+        let (merged, merged_away) = library.merge_duplicates(&clusters);
+        let track = &merged["1"];
+        assert_eq!(track.play_count, 14);
+        assert_eq!(track.skip_count, 2);
+        assert_eq!(track.play_date, Some(date("2024-06-01T00:00:00Z")));
+        assert_eq!(track.rating, 80);
+        assert!(track.loved);
+        assert_eq!(merged_away, HashSet::from(["2".to_owned()]));
+    }
+}
@@ -0,0 +1,291 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::{track::Track, ArtistCount, Library, TrackID};
+
+/// How the play count of an album is inferred from the play counts of its tracks.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AlbumStrategy {
+    /// The album has been played as many times as every track has been played.
+    #[default]
+    Minimum,
+    Median,
+    /// The mean, rounded down.
+    MeanFloor,
+    /// The minimum, ignoring short, bonus, and disabled tracks.
+    MinimumIgnoring,
+    /// The given percentile, by nearest rank.
+    Percentile,
+}
+
+/// A strategy, with the settings used by some strategies.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AlbumInference {
+    pub strategy: AlbumStrategy,
+    /// For `MinimumIgnoring`, tracks shorter than this are ignored.
+    pub min_seconds: u64,
+    /// For `Percentile`, from 0 to 100.
+    pub percentile: f64,
+}
+
+impl Default for AlbumInference {
+    fn default() -> Self {
+        AlbumInference {
+            strategy: AlbumStrategy::Minimum,
+            min_seconds: 60,
+            percentile: 25.0,
+        }
+    }
+}
+
+/// The play count of an album, with the tracks which decided it.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AlbumCount {
+    pub count: usize,
+    pub strategy: AlbumStrategy,
+    pub deciding_tracks: Vec<TrackID>,
+    pub ignored_tracks: Vec<TrackID>,
+}
+
+fn is_bonus(track: &Track) -> bool {
+    track
+        .title
+        .as_ref()
+        .is_some_and(|title| title.to_lowercase().contains("bonus"))
+}
+
+impl AlbumInference {
+    fn ignores(&self, track: &Track) -> bool {
+        self.strategy == AlbumStrategy::MinimumIgnoring
+            && (track.disabled || is_bonus(track) || track.duration.as_secs() < self.min_seconds)
+    }
+
+    /// Infers the play count of an album from its tracks.
+    pub fn infer(&self, tracks: &[&Track]) -> AlbumCount {
+        let (ignored, mut counted): (Vec<&Track>, Vec<&Track>) =
+            tracks.iter().partition(|track| self.ignores(track));
+        // with every track ignored, the album is judged on all of them
+        let mut ignored = match counted.is_empty() {
+            true => {
+                counted = ignored;
+                vec![]
+            }
+            false => ignored,
+        };
+        ignored.sort_by_key(|track| &track.id);
+        counted.sort_by(|a, b| (a.play_count, &a.id).cmp(&(b.play_count, &b.id)));
+
+        let ids = |tracks: &[&Track]| tracks.iter().map(|t| t.id.clone()).collect::<Vec<_>>();
+        let (count, deciding) = match counted.len() {
+            0 => (0, vec![]),
+            n => match self.strategy {
+                AlbumStrategy::Minimum | AlbumStrategy::MinimumIgnoring => {
+                    let min = counted[0].play_count;
+                    let deciding: Vec<&Track> = counted
+                        .iter()
+                        .copied()
+                        .filter(|t| t.play_count == min)
+                        .collect();
+                    (min, ids(&deciding))
+                }
+                AlbumStrategy::Median => match n % 2 {
+                    1 => (counted[n / 2].play_count, ids(&counted[n / 2..=n / 2])),
+                    _ => {
+                        let middle = &counted[n / 2 - 1..=n / 2];
+                        (
+                            (middle[0].play_count + middle[1].play_count) / 2,
+                            ids(middle),
+                        )
+                    }
+                },
+                AlbumStrategy::MeanFloor => {
+                    let total: usize = counted.iter().map(|t| t.play_count).sum();
+                    (total / n, ids(&counted))
+                }
+                AlbumStrategy::Percentile => {
+                    let rank = (self.percentile.clamp(0.0, 100.0) / 100.0 * n as f64).ceil();
+                    let index = (rank as usize).clamp(1, n) - 1;
+                    (counted[index].play_count, ids(&counted[index..=index]))
+                }
+            },
+        };
+
+        AlbumCount {
+            count,
+            strategy: self.strategy,
+            deciding_tracks: deciding,
+            ignored_tracks: ids(&ignored),
+        }
+    }
+}
+
+impl Library {
+    /// Sets the play count of each album artist, as the sum of the play counts of its tracks, and of each album, using `inference`.
+    pub fn derive_artist_album_playcounts_with(&mut self, inference: &AlbumInference) {
+        let mut albums: HashMap<(&str, &str), Vec<&Track>> = HashMap::default();
+        let mut counts: HashMap<String, ArtistCount> = HashMap::default();
+        for track in self.tracks.values() {
+            let Some(artist) = track.album_artist_key() else {
+                continue;
+            };
+            counts.entry(artist.to_owned()).or_default().count += track.play_count;
+            if let Some(album) = &track.album_title {
+                albums.entry((artist, album)).or_default().push(track);
+            }
+        }
+        for ((artist, album), tracks) in albums {
+            if let Some(artist_entry) = counts.get_mut(artist) {
+                artist_entry
+                    .albums
+                    .insert(album.to_owned(), inference.infer(&tracks));
+            }
+        }
+        self.counts = counts;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::structs::track::VARIOUS_ARTISTS;
+    use crate::test_fixtures::{library, track};
+
+    fn played(id: &str, play_count: usize) -> Track {
+        Track {
+            play_count,
+            ..track(id)
+        }
+    }
+
+    fn infer(strategy: AlbumStrategy, tracks: &[Track]) -> AlbumCount {
+        let inference = AlbumInference {
+            strategy,
+            ..Default::default()
+        };
+        inference.infer(&tracks.iter().collect::<Vec<_>>())
+    }
+
+    fn album() -> Vec<Track> {
+        vec![
+            played("1", 9),
+            played("2", 1),
+            played("3", 6),
+            played("4", 4),
+        ]
+    }
+
+    #[test]
+    fn minimum() {
+        let count = infer(AlbumStrategy::Minimum, &album());
+        assert_eq!(count.count, 1);
+        assert_eq!(count.deciding_tracks, ["2"]);
+    }
+
+    #[test]
+    fn median() {
+        let count = infer(AlbumStrategy::Median, &album());
+        assert_eq!(count.count, 5);
+        assert_eq!(count.deciding_tracks, ["4", "3"]);
+
+        let mut odd = album();
+        odd.pop();
+        let count = infer(AlbumStrategy::Median, &odd);
+        assert_eq!(count.count, 6);
+        assert_eq!(count.deciding_tracks, ["3"]);
+    }
+
+    #[test]
+    fn mean_floor() {
+        let count = infer(AlbumStrategy::MeanFloor, &album());
+        assert_eq!(count.count, 5);
+        assert_eq!(count.deciding_tracks.len(), 4);
+    }
+
+    #[test]
+    fn percentile() {
+        let inference = AlbumInference {
+            strategy: AlbumStrategy::Percentile,
+            percentile: 50.0,
+            ..Default::default()
+        };
+        let tracks = album();
+        let count = inference.infer(&tracks.iter().collect::<Vec<_>>());
+        assert_eq!(count.count, 4);
+        assert_eq!(count.deciding_tracks, ["4"]);
+
+        let inference = AlbumInference {
+            percentile: 0.0,
+            ..inference
+        };
+        assert_eq!(inference.infer(&tracks.iter().collect::<Vec<_>>()).count, 1);
+    }
+
+    #[test]
+    fn minimum_ignoring() {
+        let mut tracks = album();
+        tracks[1].title = Some("Song 2 (Bonus Track)".to_owned());
+        tracks.push(Track {
+            duration: Duration::from_secs(30),
+            ..played("5", 0)
+        });
+        tracks.push(Track {
+            disabled: true,
+            ..played("6", 0)
+        });
+        let count = infer(AlbumStrategy::MinimumIgnoring, &tracks);
+        assert_eq!(count.count, 4);
+        assert_eq!(count.deciding_tracks, ["4"]);
+        assert_eq!(count.ignored_tracks, ["2", "5", "6"]);
+    }
+
+    #[test]
+    fn minimum_ignoring_every_track() {
+        let tracks = vec![
+            Track {
+                duration: Duration::from_secs(30),
+                ..played("1", 3)
+            },
+            Track {
+                duration: Duration::from_secs(30),
+                ..played("2", 2)
+            },
+        ];
+        let count = infer(AlbumStrategy::MinimumIgnoring, &tracks);
+        assert_eq!(count.count, 2);
+        assert!(count.ignored_tracks.is_empty());
+    }
+
+    #[test]
+    fn no_tracks() {
+        assert_eq!(infer(AlbumStrategy::Median, &[]).count, 0);
+    }
+
+    #[test]
+    fn albums_grouped_by_album_artist() {
+        let mut library = library(
+            [
+                ("1", "Band", None, false, 2),
+                ("2", "Band feat. Guest", Some("Band"), false, 3),
+                ("3", "Solo", None, true, 5),
+            ]
+            .map(
+                |(id, artist, album_artist, compilation, play_count)| Track {
+                    artist: Some(artist.to_owned()),
+                    album_artist: album_artist.map(str::to_owned),
+                    album_title: Some("Album".to_owned()),
+                    compilation,
+                    ..played(id, play_count)
+                },
+            ),
+        );
+        library.derive_artist_album_playcounts();
+        assert_eq!(library.counts["Band"].count, 5);
+        assert_eq!(library.counts["Band"].albums["Album"].count, 2);
+        assert_eq!(library.counts[VARIOUS_ARTISTS].albums["Album"].count, 5);
+    }
+}
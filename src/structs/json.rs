@@ -0,0 +1,202 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use super::Library;
+
+/// The version of the JSON format written by [`Library::json_export`].
+///
+/// - 0: no version field, with durations as `{"secs", "nanos"}` and the misspelt `compiltion` and `release_data` fields.
+/// - 1: a `version` field, with durations as `duration_ms` and the fields `compilation` and `release_date`.
+/// - 2: album play counts as objects, with the strategy and tracks which decided the count.
+pub const JSON_VERSION: u64 = 2;
+
+#[derive(Serialize)]
+pub(super) struct Versioned<'l> {
+    pub version: u64,
+    #[serde(flatten)]
+    pub library: &'l Library,
+}
+
+/// Durations as a whole number of milliseconds, as in the Apple Music library.
+pub mod duration_ms {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_millis() as u64)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        Ok(Duration::from_millis(u64::deserialize(deserializer)?))
+    }
+}
+
+fn rename(track: &mut Map<String, Value>, from: &str, to: &str) {
+    if let Some(value) = track.remove(from) {
+        track.insert(to.to_owned(), value);
+    }
+}
+
+fn migrate_track_0(track: &mut Map<String, Value>) {
+    rename(track, "compiltion", "compilation");
+    rename(track, "release_data", "release_date");
+    if let Some(duration) = track.remove("duration") {
+        let secs = duration.get("secs").and_then(Value::as_u64).unwrap_or(0);
+        let nanos = duration.get("nanos").and_then(Value::as_u64).unwrap_or(0);
+        track.insert(
+            "duration_ms".to_owned(),
+            Value::from(secs * 1000 + nanos / 1_000_000),
+        );
+    }
+}
+
+fn migrate_counts_1(library: &mut Map<String, Value>) {
+    let Some(Value::Object(artists)) = library.get_mut("counts") else {
+        return;
+    };
+    for artist in artists.values_mut() {
+        if let Some(Value::Object(albums)) = artist.get_mut("albums") {
+            for album in albums.values_mut() {
+                if album.is_number() {
+                    *album = serde_json::json!({ "count": album });
+                }
+            }
+        }
+    }
+}
+
+/// Updates a library written in an earlier version of the JSON format to the current version.
+pub fn migrate(library: &mut Map<String, Value>) -> Result<(), std::io::Error> {
+    let version = match library.remove("version") {
+        Some(version) => version.as_u64().ok_or(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("The version of the library JSON is not a number: {version}"),
+        ))?,
+        None => 0,
+    };
+    if version > JSON_VERSION {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("The library JSON is version {version}, but only versions up to {JSON_VERSION} can be read"),
+        ));
+    }
+
+    if version < 1 {
+        if let Some(Value::Object(tracks)) = library.get_mut("tracks") {
+            for track in tracks.values_mut() {
+                if let Value::Object(track) = track {
+                    migrate_track_0(track);
+                }
+            }
+        }
+    }
+    if version < 2 {
+        migrate_counts_1(library);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::structs::{inference::AlbumCount, track::Track, ArtistCount};
+    use crate::test_fixtures::{self, date, track};
+
+    fn library() -> Library {
+        let mut library = test_fixtures::library([Track {
+            duration: Duration::from_millis(200_500),
+            compilation: true,
+            release_date: Some(date("2020-01-01T00:00:00Z")),
+            play_count: 3,
+            ..track("1")
+        }]);
+        let mut artist = ArtistCount {
+            count: 3,
+            ..Default::default()
+        };
+        artist.albums.insert(
+            "First".to_owned(),
+            AlbumCount {
+                count: 3,
+                ..Default::default()
+            },
+        );
+        library.counts.insert("Band".to_owned(), artist);
+        library
+    }
+
+    /// The library as written by the current version.
+    fn current() -> Map<String, Value> {
+        let value = serde_json::to_value(Versioned {
+            version: JSON_VERSION,
+            library: &library(),
+        })
+        .unwrap();
+        let Value::Object(map) = value else {
+            panic!("a library is written as an object")
+        };
+        map
+    }
+
+    fn read(mut written: Map<String, Value>) -> Library {
+        migrate(&mut written).unwrap();
+        serde_json::from_value(Value::Object(written)).unwrap()
+    }
+
+    fn album_counts_as_numbers(written: &mut Map<String, Value>) {
+        written["counts"]["Band"]["albums"]["First"] = json!(3);
+    }
+
+    fn assert_read(library: &Library) {
+        let track = &library.tracks["1"];
+        assert_eq!(track.duration, Duration::from_millis(200_500));
+        assert!(track.compilation);
+        assert_eq!(track.release_date, Some(date("2020-01-01T00:00:00Z")));
+        assert_eq!(library.counts["Band"].albums["First"].count, 3);
+    }
+
+    #[test]
+    fn reads_the_current_version() {
+        assert_read(&read(current()));
+    }
+
+    #[test]
+    fn migrates_version_1() {
+        let mut written = current();
+        written["version"] = json!(1);
+        album_counts_as_numbers(&mut written);
+        assert_read(&read(written));
+    }
+
+    #[test]
+    fn migrates_version_0() {
+        let mut written = current();
+        written.remove("version");
+        album_counts_as_numbers(&mut written);
+        let Value::Object(track) = &mut written["tracks"]["1"] else {
+            panic!("a track is written as an object")
+        };
+        rename(track, "compilation", "compiltion");
+        rename(track, "release_date", "release_data");
+        track.remove("duration_ms");
+        track.insert(
+            "duration".to_owned(),
+            json!({ "secs": 200, "nanos": 500_000_000 }),
+        );
+        assert_read(&read(written));
+    }
+
+    #[test]
+    fn refuses_later_versions() {
+        let mut written = current();
+        written["version"] = json!(JSON_VERSION + 1);
+        assert!(migrate(&mut written).is_err());
+        written.insert("version".to_owned(), json!("two"));
+        assert!(migrate(&mut written).is_err());
+    }
+}
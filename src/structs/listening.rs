@@ -0,0 +1,442 @@
+use std::fmt::Write as _;
+use std::path::Path;
+use std::time::Duration;
+
+use chrono::{Datelike, TimeDelta};
+
+use super::{
+    query::{Grouping, Measure, Query, Sort},
+    track::Track,
+    Library,
+};
+
+/// A table of the listening report, with each value already formatted.
+#[derive(Debug, Default)]
+pub struct Section {
+    pub heading: String,
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+/// Statistics on what has been listened to, taken entirely from a library.
+#[derive(Debug, Default)]
+pub struct ListeningReport {
+    pub title: String,
+    /// Notes on what the report covers.
+    pub notes: Vec<String>,
+    pub sections: Vec<Section>,
+}
+
+fn hours(duration: Duration) -> String {
+    format!("{:.1}", duration.as_secs_f64() / 3600.0)
+}
+
+fn describe(track: &Track) -> String {
+    format!(
+        "{} - {}",
+        track.artist.as_deref().unwrap_or("[No artist]"),
+        track.title.as_deref().unwrap_or("[No title]")
+    )
+}
+
+fn section(heading: &str, columns: &[&str], rows: Vec<Vec<String>>) -> Section {
+    Section {
+        heading: heading.to_owned(),
+        columns: columns.iter().map(|c| c.to_string()).collect(),
+        rows,
+    }
+}
+
+fn group_sections(
+    query: &Query,
+    (name, plural): (&str, &str),
+    grouping: Grouping,
+    top: usize,
+) -> [Section; 2] {
+    let columns = [name, "Plays", "Hours", "Tracks"];
+    let rows = |measure| {
+        query
+            .top_groups(grouping, measure, top)
+            .into_iter()
+            .map(|group| {
+                vec![
+                    group.key,
+                    group.stats.plays.to_string(),
+                    hours(group.stats.listening_time),
+                    group.stats.tracks.to_string(),
+                ]
+            })
+            .collect()
+    };
+    [
+        section(
+            &format!("Top {plural} by plays"),
+            &columns,
+            rows(Measure::Plays),
+        ),
+        section(
+            &format!("Top {plural} by listening time"),
+            &columns,
+            rows(Measure::ListeningTime),
+        ),
+    ]
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn escape_markdown(text: &str) -> String {
+    text.replace('|', "\\|")
+}
+
+impl ListeningReport {
+    /// The report for the whole library, or, with a year, for the tracks last played in that year.
+    ///
+    /// Lists have at most `top` entries.
+    pub fn from_library(library: &Library, year: Option<i32>, top: usize) -> Self {
+        let query = || match year {
+            Some(year) => library
+                .query()
+                .filter(move |t| t.play_date.is_some_and(|played| played.year() == year)),
+            None => library.query(),
+        };
+        let mut report = ListeningReport {
+            title: match year {
+                Some(year) => format!("{year} in review"),
+                None => "Listening report".to_owned(),
+            },
+            ..Default::default()
+        };
+        report
+            .notes
+            .push(format!("From the library of {}.", library.date));
+        if year.is_some() {
+            // Apple Music keeps only the last play of each track
+            report.notes.push(
+                "Covers the tracks last played in the year, with the plays of all time.".to_owned(),
+            );
+        }
+
+        let totals = query().stats();
+        report.sections.push(section(
+            "Summary",
+            &["", "Total"],
+            vec![
+                vec!["Tracks".to_owned(), totals.tracks.to_string()],
+                vec!["Plays".to_owned(), totals.plays.to_string()],
+                vec!["Hours listened".to_owned(), hours(totals.listening_time)],
+                vec!["Skips".to_owned(), totals.skips.to_string()],
+                vec!["Loved or favourited".to_owned(), totals.loved.to_string()],
+                vec!["Never played".to_owned(), totals.never_played.to_string()],
+            ],
+        ));
+
+        for (name, grouping) in [
+            (("Artist", "artists"), Grouping::AlbumArtist),
+            (("Album", "albums"), Grouping::Album),
+            (("Genre", "genres"), Grouping::Genre),
+            (("Decade", "decades"), Grouping::Decade),
+        ] {
+            report
+                .sections
+                .extend(group_sections(&query(), name, grouping, top));
+        }
+
+        let skipped = query()
+            .filter(|t| t.skip_count > 0)
+            .top(Sort::Skips, top)
+            .into_iter()
+            .map(|t| {
+                vec![
+                    describe(t),
+                    t.skip_count.to_string(),
+                    t.play_count.to_string(),
+                ]
+            })
+            .collect();
+        report.sections.push(section(
+            "Most skipped tracks",
+            &["Track", "Skips", "Plays"],
+            skipped,
+        ));
+
+        // loved tracks not played in the year before the library was exported, longest neglected first
+        let a_year_ago = library.date - TimeDelta::days(365);
+        let mut neglected = library
+            .query()
+            .loved()
+            .filter(move |t| t.play_date.is_none_or(|played| played < a_year_ago))
+            .sorted(Sort::PlayDate);
+        neglected.reverse();
+        neglected.truncate(top);
+        report.sections.push(section(
+            "Neglected loved tracks",
+            &["Track", "Last played", "Plays"],
+            neglected
+                .into_iter()
+                .map(|t| {
+                    vec![
+                        describe(t),
+                        t.play_date
+                            .map(|played| played.date_naive().to_string())
+                            .unwrap_or("never".to_owned()),
+                        t.play_count.to_string(),
+                    ]
+                })
+                .collect(),
+        ));
+
+        let mut total = 0;
+        let growth = library
+            .query()
+            .added_per_year()
+            .into_iter()
+            .map(|(year, added)| {
+                total += added;
+                vec![year.to_string(), added.to_string(), total.to_string()]
+            })
+            .collect();
+        report.sections.push(section(
+            "Library growth",
+            &["Year", "Tracks added", "Library size"],
+            growth,
+        ));
+
+        let mut playlists: Vec<_> = library
+            .playlists
+            .iter()
+            .filter(|playlist| !playlist.folder)
+            .map(|playlist| {
+                let tracks = playlist
+                    .track_ids
+                    .iter()
+                    .filter_map(|id| library.tracks.get(id));
+                let duration: Duration = tracks.map(|t| t.duration).sum();
+                (playlist, duration)
+            })
+            .collect();
+        playlists.sort_by(|(a, _), (b, _)| {
+            b.track_ids
+                .len()
+                .cmp(&a.track_ids.len())
+                .then(a.name.cmp(&b.name))
+        });
+        report.sections.push(section(
+            "Playlist sizes",
+            &["Playlist", "Tracks", "Hours"],
+            playlists
+                .into_iter()
+                .map(|(playlist, duration)| {
+                    vec![
+                        playlist.name.clone(),
+                        playlist.track_ids.len().to_string(),
+                        hours(duration),
+                    ]
+                })
+                .collect(),
+        ));
+
+        report
+    }
+
+    pub fn to_markdown(&self) -> String {
+        // writing to a string does not fail
+        let mut md = String::new();
+        let _ = writeln!(md, "# {}\n", self.title);
+        for note in &self.notes {
+            let _ = writeln!(md, "{note}");
+        }
+        for section in &self.sections {
+            let _ = writeln!(md, "\n## {}\n", section.heading);
+            if section.rows.is_empty() {
+                let _ = writeln!(md, "None");
+                continue;
+            }
+            let _ = writeln!(md, "| {} |", section.columns.join(" | "));
+            let _ = writeln!(md, "|{}", " --- |".repeat(section.columns.len()));
+            for row in &section.rows {
+                let row: Vec<String> = row.iter().map(|value| escape_markdown(value)).collect();
+                let _ = writeln!(md, "| {} |", row.join(" | "));
+            }
+        }
+        md
+    }
+
+    /// A single HTML page, with its styles inline so it can be opened offline.
+    pub fn to_html(&self) -> String {
+        let mut html = String::new();
+        let _ = writeln!(html, "<!DOCTYPE html>\n<html lang=\"en\">\n<head>");
+        let _ = writeln!(html, "<meta charset=\"utf-8\">");
+        let _ = writeln!(html, "<title>{}</title>", escape_html(&self.title));
+        let _ = writeln!(
+            html,
+            "<style>\n\
+body {{ font-family: sans-serif; max-width: 60em; margin: 2em auto; padding: 0 1em; color: #222; }}\n\
+table {{ border-collapse: collapse; margin-bottom: 1em; }}\n\
+th, td {{ padding: 0.25em 0.75em; border-bottom: 1px solid #ddd; text-align: left; }}\n\
+td:not(:first-child) {{ text-align: right; }}\n\
+</style>"
+        );
+        let _ = writeln!(html, "</head>\n<body>");
+        let _ = writeln!(html, "<h1>{}</h1>", escape_html(&self.title));
+        for note in &self.notes {
+            let _ = writeln!(html, "<p>{}</p>", escape_html(note));
+        }
+        for section in &self.sections {
+            let _ = writeln!(html, "<h2>{}</h2>", escape_html(&section.heading));
+            if section.rows.is_empty() {
+                let _ = writeln!(html, "<p>None</p>");
+                continue;
+            }
+            let _ = writeln!(html, "<table>\n<tr>");
+            for column in &section.columns {
+                let _ = writeln!(html, "<th>{}</th>", escape_html(column));
+            }
+            let _ = writeln!(html, "</tr>");
+            for row in &section.rows {
+                let _ = write!(html, "<tr>");
+                for value in row {
+                    let _ = write!(html, "<td>{}</td>", escape_html(value));
+                }
+                let _ = writeln!(html, "</tr>");
+            }
+            let _ = writeln!(html, "</table>");
+        }
+        let _ = writeln!(html, "</body>\n</html>");
+        html
+    }
+
+    pub fn markdown_export(&self, path: &Path) -> Result<(), std::io::Error> {
+        std::fs::write(path, self.to_markdown())
+    }
+
+    pub fn html_export(&self, path: &Path) -> Result<(), std::io::Error> {
+        std::fs::write(path, self.to_html())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::playlist::Playlist;
+    use crate::test_fixtures::{date, library, track};
+
+    fn tracks() -> Library {
+        let mut library = library([
+            Track {
+                play_count: 10,
+                skip_count: 1,
+                loved: true,
+                play_date: Some(date("2024-06-01T00:00:00Z")),
+                date_added: date("2022-01-01T00:00:00Z"),
+                ..track("1")
+            },
+            Track {
+                artist: Some("Solo | Guest".to_owned()),
+                play_count: 2,
+                skip_count: 4,
+                loved: true,
+                play_date: Some(date("2023-01-01T00:00:00Z")),
+                date_added: date("2023-01-01T00:00:00Z"),
+                ..track("2")
+            },
+            Track {
+                favourited: true,
+                date_added: date("2023-02-01T00:00:00Z"),
+                ..track("3")
+            },
+        ]);
+        library.date = date("2024-12-31T00:00:00Z");
+        library.playlists = vec![
+            Playlist {
+                name: "Folder".to_owned(),
+                folder: true,
+                track_ids: vec!["1".to_owned(), "2".to_owned(), "3".to_owned()],
+                ..Default::default()
+            },
+            Playlist {
+                name: "Mix".to_owned(),
+                track_ids: vec!["1".to_owned(), "2".to_owned()],
+                ..Default::default()
+            },
+        ];
+        library
+    }
+
+    fn rows<'r>(report: &'r ListeningReport, heading: &str) -> &'r [Vec<String>] {
+        &report
+            .sections
+            .iter()
+            .find(|section| section.heading == heading)
+            .unwrap()
+            .rows
+    }
+
+    #[test]
+    fn whole_library() {
+        let report = ListeningReport::from_library(&tracks(), None, 10);
+        assert_eq!(report.title, "Listening report");
+        assert_eq!(rows(&report, "Summary")[1], ["Plays", "12"]);
+        assert_eq!(
+            rows(&report, "Top artists by plays"),
+            [
+                ["Band", "10", "0.6", "2"],
+                ["Solo | Guest", "2", "0.1", "1"]
+            ]
+        );
+        assert_eq!(
+            rows(&report, "Most skipped tracks"),
+            [
+                ["Solo | Guest - Song 2", "4", "2"],
+                ["Band - Song 1", "1", "10"]
+            ]
+        );
+        // loved tracks not played since a year before the library, never played first
+        assert_eq!(
+            rows(&report, "Neglected loved tracks"),
+            [
+                ["Band - Song 3", "never", "0"],
+                ["Solo | Guest - Song 2", "2023-01-01", "2"]
+            ]
+        );
+        assert_eq!(
+            rows(&report, "Library growth"),
+            [["2022", "1", "1"], ["2023", "2", "3"]]
+        );
+        // folders are left out
+        assert_eq!(rows(&report, "Playlist sizes"), [["Mix", "2", "0.1"]]);
+    }
+
+    #[test]
+    fn year_in_review() {
+        let report = ListeningReport::from_library(&tracks(), Some(2024), 1);
+        assert_eq!(report.title, "2024 in review");
+        assert_eq!(report.notes.len(), 2);
+        assert_eq!(rows(&report, "Summary")[0], ["Tracks", "1"]);
+        assert_eq!(
+            rows(&report, "Top albums by plays"),
+            [["Band - First", "10", "0.6", "1"]]
+        );
+        // the neglected tracks and growth are of the whole library, limited to `top`
+        assert_eq!(rows(&report, "Neglected loved tracks").len(), 1);
+        assert_eq!(rows(&report, "Library growth").len(), 2);
+    }
+
+    #[test]
+    fn escapes_values() {
+        let report = ListeningReport::from_library(&tracks(), None, 10);
+        let markdown = report.to_markdown();
+        assert!(markdown.contains("| Solo \\| Guest | 2 | 0.1 | 1 |"));
+        assert!(markdown.contains("## Top decades by plays\n\nNone"));
+        let html = ListeningReport {
+            title: "<Mine> & yours".to_owned(),
+            ..Default::default()
+        }
+        .to_html();
+        assert!(html.contains("<title>&lt;Mine&gt; &amp; yours</title>"));
+    }
+}
@@ -2,10 +2,20 @@ use chrono::DateTime;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+use inference::{AlbumCount, AlbumInference};
 use playlist::Playlist;
 use track::Track;
 
+pub mod checks;
+pub mod credits;
+pub mod diff;
+pub mod duplicates;
+pub mod inference;
+pub mod json;
+pub mod listening;
 pub mod playlist;
+pub mod query;
+pub mod tabular;
 pub mod track;
 
 pub type TrackID = String;
@@ -16,6 +26,12 @@ type Album = String;
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Library {
     pub date: DateTime<chrono::Utc>,
+    #[serde(default)]
+    pub application_version: Option<String>,
+    #[serde(default)]
+    pub music_folder: Option<String>,
+    #[serde(default)]
+    pub library_persistent_id: Option<String>,
     pub tracks: HashMap<TrackID, Track>,
     pub playlists: Vec<Playlist>,
     pub counts: HashMap<Artist, ArtistCount>
@@ -24,43 +40,44 @@ pub struct Library {
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct ArtistCount {
     pub count: usize,
-    pub albums: HashMap<Album, usize>,
+    pub albums: HashMap<Album, AlbumCount>,
 }
 
 impl Library {
+    /// Sets artist and album play counts, with an album counted as played only if every track has been played.
     pub fn derive_artist_album_playcounts(&mut self) {
-        'track_loop: for track in self.tracks.values() {
-            let artist = match &track.artist {
-                Some(found) => found,
-                None => continue 'track_loop,
-            };
-            let artist_entry = self.counts
-                .entry(artist.to_owned())
-                .or_default();
-            artist_entry.count += track.play_count;
-            if let Some(album) = &track.album_title {
-                let ac = artist_entry
-                    .albums
-                    .entry(album.to_owned())
-                    .or_insert(track.play_count);
-                *artist_entry.albums.get_mut(album).unwrap() = std::cmp::min(track.play_count, *ac);
-            }
-        }
+        self.derive_artist_album_playcounts_with(&AlbumInference::default());
     }
 }
 
 impl Library {
+    /// The path of the music folder, decoded from the location in the library.
+    pub fn music_folder_path(&self) -> Option<std::path::PathBuf> {
+        let location = self.music_folder.as_ref()?;
+        crate::xml_reader::tracks::decode_location(location).map(std::path::PathBuf::from)
+    }
+}
+
+impl Library {
+    /// Writes the library as JSON, in the current version of the format.
     pub fn json_export(&self, path: &std::path::Path) -> Result<(), std::io::Error> {
-        let library_json = serde_json::to_string_pretty(self)?;
+        let library_json = serde_json::to_string_pretty(&json::Versioned {
+            version: json::JSON_VERSION,
+            library: self,
+        })?;
         let mut file = std::fs::File::create(path)?;
         std::io::Write::write_all(&mut file, library_json.as_bytes())?;
         Ok(())
     }
 
+    /// Reads a library written by [`Library::json_export`], from any version of the format.
     pub fn from_json(path: &std::path::Path) -> Result<Self, std::io::Error> {
         let file = std::fs::File::open(path)?;
         let reader = std::io::BufReader::new(file);
-        let library: Library = serde_json::from_reader(reader)?;
+        let mut value: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_reader(reader)?;
+        json::migrate(&mut value)?;
+        let library: Library = serde_json::from_value(serde_json::Value::Object(value))?;
         Ok(library)
     }
 }
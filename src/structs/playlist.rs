@@ -18,48 +18,116 @@ pub struct Playlist {
 }
 
 impl Playlist {
+    /// The name of the playlist as a file name with `extension`, with characters not allowed in file names replaced.
+    pub fn file_name(&self, extension: &str) -> String {
+        let name: String = self
+            .name
+            .chars()
+            .map(|c| match c {
+                '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+                c if c.is_control() => '_',
+                c => c,
+            })
+            .collect();
+        let name = name.trim().trim_start_matches('.');
+        match name.is_empty() {
+            true => format!("{}.{extension}", self.persistent_id),
+            false => format!("{name}.{extension}"),
+        }
+    }
+
+    /// Writes the playlist to `path` as an m3u file named after it, leaving out tracks which are not in `tracks`.
     pub fn export_m3u(
         &self,
         path: &Path,
         tracks: &TrackMap,
     ) -> Result<(), xml_reader::err::LibraryXmlReader> {
-        let playlist_filename = format!("{}.m3u", self.name);
-        let playlist_path = path.join(Path::new(&playlist_filename));
-        match File::create(playlist_path.clone()) {
-            Ok(mut file) => {
-                writeln!(file, "#EXTM3U")?;
-                writeln!(file, "#PLAYLIST:{}", self.name)?;
-                for id in &self.track_ids {
-                    let track = match tracks.get(id) {
-                        Some(t) => t,
-                        None => {
-                            return Err(xml_reader::err::LibraryXmlReader::MissingTrack {
-                                playlist: self.name.to_owned(),
-                                track_id: id.clone(),
-                            })
-                        }
-                    };
-                    let title = match &track.title {
-                        Some(found) => found,
-                        None => "[No title]",
-                    };
-                    let artist = match &track.artist {
-                        Some(found) => found,
-                        None => "[No artist]",
-                    };
-                    writeln!(
-                        file,
-                        "#EXTINF:{},{artist} - {title}",
-                        track.duration.as_secs(),
-                    )?;
-                    let abs_pth = &track.location;
-                    writeln!(file, "{}", abs_pth)?;
-                }
-            }
-            Err(_) => {
-                log::error!("Failed to create a file for playlist {}", self.name);
-            }
+        let playlist_path = path.join(self.file_name("m3u"));
+        let mut file = File::create(playlist_path)?;
+        writeln!(file, "#EXTM3U")?;
+        writeln!(file, "#PLAYLIST:{}", self.name)?;
+        for id in &self.track_ids {
+            // tracks missing from the library are left out, and found by the validate command
+            let Some(track) = tracks.get(id) else {
+                log::warn!(
+                    "Playlist {} has track {id}, which is not in the library",
+                    self.name
+                );
+                continue;
+            };
+            let title = match &track.title {
+                Some(found) => found,
+                None => "[No title]",
+            };
+            let artist = match &track.artist {
+                Some(found) => found,
+                None => "[No artist]",
+            };
+            writeln!(
+                file,
+                "#EXTINF:{},{artist} - {title}",
+                track.duration.as_secs(),
+            )?;
+            let abs_pth = &track.location;
+            writeln!(file, "{}", abs_pth)?;
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{library, track};
+
+    fn playlist(name: &str) -> Playlist {
+        Playlist {
+            name: name.to_owned(),
+            track_ids: vec!["1".to_owned(), "2".to_owned()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn writes_m3u() {
+        let directory = std::env::temp_dir().join(format!("an_m3u_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let library = library([Track {
+            location: "file:///Music/1.mp3".to_owned(),
+            ..track("1")
+        }]);
+        playlist("Mix")
+            .export_m3u(&directory, &library.tracks)
+            .unwrap();
+        let m3u = std::fs::read_to_string(directory.join("Mix.m3u")).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(
+            m3u,
+            "#EXTM3U\n#PLAYLIST:Mix\n#EXTINF:200,Band - Song 1\nfile:///Music/1.mp3\n"
+        );
+    }
+
+    #[test]
+    fn file_names() {
+        assert_eq!(playlist("Mix").file_name("xml"), "Mix.xml");
+        assert_eq!(
+            playlist("AC/DC: Live?").file_name("m3u"),
+            "AC_DC_ Live_.m3u"
+        );
+        for name in ["", ".."] {
+            let unnamed = Playlist {
+                persistent_id: "ABC".to_owned(),
+                ..playlist(name)
+            };
+            assert_eq!(unnamed.file_name("xml"), "ABC.xml");
+        }
+    }
+
+    #[test]
+    fn returns_the_error_creating_the_file() {
+        let missing = std::env::temp_dir().join("an_m3u_missing").join("folder");
+        assert!(playlist("Mix")
+            .export_m3u(&missing, &library([]).tracks)
+            .is_err());
+    }
+}
@@ -0,0 +1,408 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use chrono::{DateTime, Datelike, Utc};
+
+use super::{track::Track, Library};
+
+/// How tracks are ordered, with the largest, or latest, first.
+///
+/// Text fields are ordered alphabetically instead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sort {
+    Plays,
+    ListeningTime,
+    Skips,
+    Rating,
+    Duration,
+    DateAdded,
+    PlayDate,
+    Year,
+    Title,
+    Artist,
+    Album,
+}
+
+fn text_order(a: &Option<String>, b: &Option<String>) -> Ordering {
+    let lower = |s: &Option<String>| s.as_ref().map(|s| s.to_lowercase());
+    // tracks without a value come last
+    match (lower(a), lower(b)) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (a, b) => a.is_none().cmp(&b.is_none()),
+    }
+}
+
+impl Sort {
+    fn compare(self, a: &Track, b: &Track) -> Ordering {
+        match self {
+            Sort::Plays => b.play_count.cmp(&a.play_count),
+            Sort::ListeningTime => b.listening_time().cmp(&a.listening_time()),
+            Sort::Skips => b.skip_count.cmp(&a.skip_count),
+            Sort::Rating => b.rating.cmp(&a.rating),
+            Sort::Duration => b.duration.cmp(&a.duration),
+            Sort::DateAdded => b.date_added.cmp(&a.date_added),
+            Sort::PlayDate => b.play_date.cmp(&a.play_date),
+            Sort::Year => b.year.cmp(&a.year),
+            Sort::Title => text_order(&a.title, &b.title),
+            Sort::Artist => text_order(&a.artist, &b.artist),
+            Sort::Album => text_order(&a.album_title, &b.album_title),
+        }
+    }
+}
+
+/// How tracks are grouped. Tracks without a value for the grouping are left out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Grouping {
+    Artist,
+    /// As with album play counts, see [`Track::album_artist_key`].
+    AlbumArtist,
+    /// Albums, named as "album artist - album".
+    Album,
+    Genre,
+    Year,
+    Decade,
+}
+
+impl Grouping {
+    fn key(self, track: &Track) -> Option<String> {
+        match self {
+            Grouping::Artist => track.artist.clone(),
+            Grouping::AlbumArtist => track.album_artist_key().map(str::to_owned),
+            Grouping::Album => match (track.album_artist_key(), &track.album_title) {
+                (Some(artist), Some(album)) => Some(format!("{artist} - {album}")),
+                (None, Some(album)) => Some(album.clone()),
+                (_, None) => None,
+            },
+            Grouping::Genre => track.genre.clone(),
+            Grouping::Year => track.year.map(|year| year.to_string()),
+            Grouping::Decade => track.year.map(|year| format!("{}s", year / 10 * 10)),
+        }
+    }
+}
+
+/// Totals over some tracks.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub tracks: usize,
+    pub plays: usize,
+    pub skips: usize,
+    /// The sum of play count × duration.
+    pub listening_time: Duration,
+    pub duration: Duration,
+    pub loved: usize,
+    pub never_played: usize,
+}
+
+impl Stats {
+    pub fn of<'t>(tracks: impl IntoIterator<Item = &'t Track>) -> Self {
+        let mut stats = Stats::default();
+        for track in tracks {
+            stats.tracks += 1;
+            stats.plays += track.play_count;
+            stats.skips += track.skip_count;
+            stats.listening_time += track.listening_time();
+            stats.duration += track.duration;
+            stats.loved += (track.loved || track.favourited) as usize;
+            stats.never_played += (track.play_count == 0) as usize;
+        }
+        stats
+    }
+}
+
+/// What groups are ranked by, largest first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Measure {
+    Plays,
+    ListeningTime,
+    Skips,
+    Tracks,
+}
+
+impl Measure {
+    fn compare(self, a: &Stats, b: &Stats) -> Ordering {
+        match self {
+            Measure::Plays => b.plays.cmp(&a.plays),
+            Measure::ListeningTime => b.listening_time.cmp(&a.listening_time),
+            Measure::Skips => b.skips.cmp(&a.skips),
+            Measure::Tracks => b.tracks.cmp(&a.tracks),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Group<'l> {
+    pub key: String,
+    pub tracks: Vec<&'l Track>,
+    pub stats: Stats,
+}
+
+type Filter<'l> = Box<dyn Fn(&Track) -> bool + 'l>;
+
+/// The tracks of a library which pass every filter, as in `library.query().year(2019).groups(Grouping::Album, Measure::Plays)`.
+pub struct Query<'l> {
+    library: &'l Library,
+    filters: Vec<Filter<'l>>,
+}
+
+fn same_text(value: &Option<String>, text: &str) -> bool {
+    value
+        .as_ref()
+        .is_some_and(|value| value.to_lowercase() == text.to_lowercase())
+}
+
+impl Library {
+    /// A query over every track, to be narrowed with filters.
+    pub fn query(&self) -> Query<'_> {
+        Query {
+            library: self,
+            filters: vec![],
+        }
+    }
+}
+
+impl<'l> Query<'l> {
+    /// Keeps the tracks for which `filter` is true, along with any other filters.
+    pub fn filter(mut self, filter: impl Fn(&Track) -> bool + 'l) -> Self {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    /// Tracks by the artist, as artist or album artist, ignoring case.
+    pub fn artist(self, artist: &str) -> Self {
+        let artist = artist.to_owned();
+        self.filter(move |t| same_text(&t.artist, &artist) || same_text(&t.album_artist, &artist))
+    }
+
+    pub fn album(self, album: &str) -> Self {
+        let album = album.to_owned();
+        self.filter(move |t| same_text(&t.album_title, &album))
+    }
+
+    pub fn genre(self, genre: &str) -> Self {
+        let genre = genre.to_owned();
+        self.filter(move |t| same_text(&t.genre, &genre))
+    }
+
+    /// Tracks released in the year.
+    pub fn year(self, year: usize) -> Self {
+        self.filter(move |t| t.year == Some(year))
+    }
+
+    pub fn added_in(self, year: i32) -> Self {
+        self.filter(move |t| t.date_added.year() == year)
+    }
+
+    /// Tracks last played at or after `date`.
+    pub fn played_since(self, date: DateTime<Utc>) -> Self {
+        self.filter(move |t| t.play_date.is_some_and(|played| played >= date))
+    }
+
+    /// Tracks loved or favourited.
+    pub fn loved(self) -> Self {
+        self.filter(|t| t.loved || t.favourited)
+    }
+
+    pub fn played(self) -> Self {
+        self.filter(|t| t.play_count > 0)
+    }
+
+    pub fn unplayed(self) -> Self {
+        self.filter(|t| t.play_count == 0)
+    }
+
+    /// The tracks which pass the filters, ordered by id.
+    pub fn tracks(&self) -> Vec<&'l Track> {
+        let mut tracks: Vec<&Track> = self
+            .library
+            .tracks
+            .values()
+            .filter(|track| self.filters.iter().all(|filter| filter(track)))
+            .collect();
+        tracks.sort_by(|a, b| a.id.cmp(&b.id));
+        tracks
+    }
+
+    pub fn count(&self) -> usize {
+        self.tracks().len()
+    }
+
+    pub fn sorted(&self, sort: Sort) -> Vec<&'l Track> {
+        let mut tracks = self.tracks();
+        // the sort is stable, so ties stay ordered by id
+        tracks.sort_by(|a, b| sort.compare(a, b));
+        tracks
+    }
+
+    /// The first `n` tracks ordered by `sort`.
+    pub fn top(&self, sort: Sort, n: usize) -> Vec<&'l Track> {
+        let mut tracks = self.sorted(sort);
+        tracks.truncate(n);
+        tracks
+    }
+
+    pub fn stats(&self) -> Stats {
+        Stats::of(self.tracks())
+    }
+
+    /// The tracks grouped by `grouping`, ranked by `measure` and then by key.
+    pub fn groups(&self, grouping: Grouping, measure: Measure) -> Vec<Group<'l>> {
+        let mut groups: HashMap<String, Vec<&Track>> = HashMap::default();
+        for track in self.tracks() {
+            if let Some(key) = grouping.key(track) {
+                groups.entry(key).or_default().push(track);
+            }
+        }
+        let mut groups: Vec<Group> = groups
+            .into_iter()
+            .map(|(key, tracks)| Group {
+                key,
+                stats: Stats::of(tracks.iter().copied()),
+                tracks,
+            })
+            .collect();
+        groups.sort_by(|a, b| measure.compare(&a.stats, &b.stats).then(a.key.cmp(&b.key)));
+        groups
+    }
+
+    /// The first `n` groups ranked by `measure`.
+    pub fn top_groups(&self, grouping: Grouping, measure: Measure, n: usize) -> Vec<Group<'l>> {
+        let mut groups = self.groups(grouping, measure);
+        groups.truncate(n);
+        groups
+    }
+
+    /// The number of tracks added to the library in each year.
+    pub fn added_per_year(&self) -> BTreeMap<i32, usize> {
+        let mut years = BTreeMap::default();
+        for track in self.tracks() {
+            *years.entry(track.date_added.year()).or_default() += 1;
+        }
+        years
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{date, library, track};
+
+    fn tracks() -> Library {
+        library([
+            Track {
+                play_count: 4,
+                year: Some(2019),
+                genre: Some("Rock".to_owned()),
+                loved: true,
+                play_date: Some(date("2024-03-01T00:00:00Z")),
+                ..track("1")
+            },
+            Track {
+                play_count: 2,
+                skip_count: 3,
+                year: Some(2019),
+                genre: Some("rock".to_owned()),
+                duration: Duration::from_secs(100),
+                album_title: Some("Second".to_owned()),
+                ..track("2")
+            },
+            Track {
+                artist: Some("Solo".to_owned()),
+                album_artist: Some("Band".to_owned()),
+                year: Some(1987),
+                ..track("3")
+            },
+            Track {
+                artist: None,
+                play_count: 1,
+                title: None,
+                date_added: date("2023-05-01T00:00:00Z"),
+                play_date: Some(date("2023-06-01T00:00:00Z")),
+                ..track("4")
+            },
+        ])
+    }
+
+    fn ids(tracks: &[&Track]) -> Vec<String> {
+        tracks.iter().map(|t| t.id.clone()).collect()
+    }
+
+    #[test]
+    fn filters() {
+        let library = tracks();
+        assert_eq!(ids(&library.query().tracks()), ["1", "2", "3", "4"]);
+        assert_eq!(ids(&library.query().year(2019).tracks()), ["1", "2"]);
+        assert_eq!(ids(&library.query().genre("ROCK").tracks()), ["1", "2"]);
+        // the album artist counts too
+        assert_eq!(
+            ids(&library.query().artist("band").tracks()),
+            ["1", "2", "3"]
+        );
+        assert_eq!(ids(&library.query().album("second").tracks()), ["2"]);
+        assert_eq!(ids(&library.query().loved().tracks()), ["1"]);
+        assert_eq!(ids(&library.query().unplayed().tracks()), ["3"]);
+        assert_eq!(ids(&library.query().added_in(2023).tracks()), ["4"]);
+        let since = library.query().played_since(date("2024-01-01T00:00:00Z"));
+        assert_eq!(ids(&since.tracks()), ["1"]);
+        // filters are combined
+        assert_eq!(library.query().year(2019).played().loved().count(), 1);
+    }
+
+    #[test]
+    fn sorts() {
+        let library = tracks();
+        let query = library.query();
+        assert_eq!(ids(&query.sorted(Sort::Plays)), ["1", "2", "4", "3"]);
+        assert_eq!(ids(&query.top(Sort::Skips, 1)), ["2"]);
+        // ties stay ordered by id, and tracks without a year or title come last
+        assert_eq!(ids(&query.sorted(Sort::Year)), ["1", "2", "3", "4"]);
+        assert_eq!(ids(&query.sorted(Sort::Title)), ["1", "2", "3", "4"]);
+        assert_eq!(ids(&query.sorted(Sort::Artist)), ["1", "2", "3", "4"]);
+        assert_eq!(
+            ids(&query.sorted(Sort::ListeningTime)),
+            ["1", "2", "4", "3"]
+        );
+    }
+
+    #[test]
+    fn group_totals() {
+        let library = tracks();
+        let groups = library
+            .query()
+            .groups(Grouping::AlbumArtist, Measure::Plays);
+        let keys: Vec<&str> = groups.iter().map(|g| g.key.as_str()).collect();
+        // track 4 has no artist, so is left out
+        assert_eq!(keys, ["Band"]);
+        assert_eq!(
+            groups[0].stats,
+            Stats {
+                tracks: 3,
+                plays: 6,
+                skips: 3,
+                listening_time: Duration::from_secs(4 * 200 + 2 * 100),
+                duration: Duration::from_secs(500),
+                loved: 1,
+                never_played: 1,
+            }
+        );
+
+        let albums = library.query().groups(Grouping::Album, Measure::Tracks);
+        // ties are ordered by key
+        let albums: Vec<(&str, usize)> = albums
+            .iter()
+            .map(|g| (g.key.as_str(), g.stats.tracks))
+            .collect();
+        assert_eq!(
+            albums,
+            [("Band - First", 2), ("Band - Second", 1), ("First", 1)]
+        );
+
+        let decades = library
+            .query()
+            .top_groups(Grouping::Decade, Measure::Plays, 1);
+        assert_eq!(decades[0].key, "2010s");
+        assert_eq!(decades[0].stats.plays, 6);
+        assert_eq!(library.query().stats().plays, 7);
+        assert_eq!(library.query().added_per_year().get(&2023), Some(&1));
+    }
+}
@@ -0,0 +1,357 @@
+use std::path::Path;
+
+use rusqlite::{params_from_iter, types::Value as SqlValue, Connection};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use super::{inference::AlbumStrategy, Library, TrackID};
+
+/// Rows with the same columns, taken from the serialized form of some items.
+///
+/// Scalar values are kept as is, and nested values are written as JSON.
+pub struct Table {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+fn flatten(value: Value) -> Value {
+    match value {
+        Value::Array(_) | Value::Object(_) => Value::String(value.to_string()),
+        scalar => scalar,
+    }
+}
+
+impl Table {
+    /// A table with a row for each item, and a column for each field of the serialized items.
+    pub fn from_items<T: Serialize>(
+        items: impl IntoIterator<Item = T>,
+    ) -> Result<Self, serde_json::Error> {
+        let mut table = Table {
+            columns: vec![],
+            rows: vec![],
+        };
+        for item in items {
+            let Value::Object(fields) = serde_json::to_value(item)? else {
+                continue;
+            };
+            table.push(fields);
+        }
+        Ok(table)
+    }
+
+    fn push(&mut self, fields: Map<String, Value>) {
+        if self.columns.is_empty() {
+            self.columns = fields.keys().cloned().collect();
+        }
+        let mut fields = fields;
+        let row = self
+            .columns
+            .iter()
+            .map(|column| flatten(fields.remove(column).unwrap_or(Value::Null)))
+            .collect();
+        self.rows.push(row);
+    }
+
+    pub fn without(mut self, column: &str) -> Self {
+        if let Some(index) = self.columns.iter().position(|c| c == column) {
+            self.columns.remove(index);
+            for row in &mut self.rows {
+                row.remove(index);
+            }
+        }
+        self
+    }
+
+    pub fn write_csv(&self, path: &Path) -> Result<(), csv::Error> {
+        let mut writer = csv::Writer::from_path(path)?;
+        writer.write_record(&self.columns)?;
+        for row in &self.rows {
+            writer.write_record(row.iter().map(|value| match value {
+                Value::Null => String::new(),
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            }))?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// The SQLite type of a column, from the first value which is not null.
+    fn column_type(&self, index: usize) -> &'static str {
+        let value = self
+            .rows
+            .iter()
+            .map(|row| &row[index])
+            .find(|value| !value.is_null());
+        match value {
+            Some(Value::Bool(_)) => "INTEGER",
+            Some(Value::Number(n)) if n.is_f64() => "REAL",
+            Some(Value::Number(_)) => "INTEGER",
+            _ => "TEXT",
+        }
+    }
+
+    /// Replaces `name` in `db` with the table, with `key` as the primary key if given.
+    pub fn write_sqlite(
+        &self,
+        db: &Connection,
+        name: &str,
+        key: Option<&str>,
+    ) -> Result<(), rusqlite::Error> {
+        let definitions: Vec<String> = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(index, column)| {
+                let primary = match Some(column.as_str()) == key {
+                    true => " PRIMARY KEY",
+                    false => "",
+                };
+                format!("\"{column}\" {}{primary}", self.column_type(index))
+            })
+            .collect();
+        db.execute(&format!("DROP TABLE IF EXISTS \"{name}\""), [])?;
+        // with nothing to export, the columns are unknown
+        if self.columns.is_empty() {
+            return Ok(());
+        }
+        db.execute(
+            &format!("CREATE TABLE \"{name}\" ({})", definitions.join(", ")),
+            [],
+        )?;
+
+        let insert = format!(
+            "INSERT INTO \"{name}\" VALUES ({})",
+            vec!["?"; self.columns.len()].join(", ")
+        );
+        let mut stmt = db.prepare(&insert)?;
+        for row in &self.rows {
+            stmt.execute(params_from_iter(row.iter().map(|value| match value {
+                Value::Null => SqlValue::Null,
+                Value::Bool(b) => SqlValue::Integer(*b as i64),
+                Value::Number(n) => match n.as_i64() {
+                    Some(i) => SqlValue::Integer(i),
+                    None => SqlValue::Real(n.as_f64().unwrap_or_default()),
+                },
+                Value::String(s) => SqlValue::Text(s.clone()),
+                other => SqlValue::Text(other.to_string()),
+            })))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct PlaylistItem<'p> {
+    playlist_persistent_id: &'p str,
+    position: usize,
+    track_id: &'p str,
+}
+
+#[derive(Serialize)]
+struct ArtistRow<'a> {
+    artist: &'a str,
+    play_count: usize,
+}
+
+#[derive(Serialize)]
+struct AlbumRow<'a> {
+    artist: &'a str,
+    album: &'a str,
+    play_count: usize,
+    strategy: AlbumStrategy,
+    deciding_tracks: &'a [TrackID],
+    ignored_tracks: &'a [TrackID],
+}
+
+impl Library {
+    fn track_table(&self) -> Result<Table, serde_json::Error> {
+        let mut tracks: Vec<_> = self.tracks.values().collect();
+        tracks.sort_by(|a, b| a.id.cmp(&b.id));
+        Table::from_items(tracks)
+    }
+
+    fn playlist_tables(&self) -> Result<(Table, Table), serde_json::Error> {
+        let playlists = Table::from_items(&self.playlists)?.without("track_ids");
+        let items = Table::from_items(self.playlists.iter().flat_map(|playlist| {
+            playlist
+                .track_ids
+                .iter()
+                .enumerate()
+                .map(|(position, track_id)| PlaylistItem {
+                    playlist_persistent_id: &playlist.persistent_id,
+                    position: position + 1,
+                    track_id,
+                })
+        }))?;
+        Ok((playlists, items))
+    }
+
+    /// Writes tracks.csv, playlists.csv, and playlist_items.csv to `directory`.
+    pub fn csv_export(&self, directory: &Path) -> Result<(), csv::Error> {
+        std::fs::create_dir_all(directory)?;
+        let (playlists, items) = self.playlist_tables().map_err(std::io::Error::from)?;
+        let tracks = self.track_table().map_err(std::io::Error::from)?;
+        tracks.write_csv(&directory.join("tracks.csv"))?;
+        playlists.write_csv(&directory.join("playlists.csv"))?;
+        items.write_csv(&directory.join("playlist_items.csv"))?;
+        Ok(())
+    }
+
+    /// Writes the library to a SQLite database, with tables for tracks, playlists, playlist items, and artist and album play counts.
+    pub fn sqlite_export(&self, path: &Path) -> Result<(), rusqlite::Error> {
+        let to_sql = |e: serde_json::Error| rusqlite::Error::ToSqlConversionFailure(Box::new(e));
+        let tracks = self.track_table().map_err(to_sql)?;
+        let (playlists, items) = self.playlist_tables().map_err(to_sql)?;
+
+        let mut artists: Vec<ArtistRow> = vec![];
+        let mut albums: Vec<AlbumRow> = vec![];
+        for (artist, counts) in &self.counts {
+            artists.push(ArtistRow {
+                artist,
+                play_count: counts.count,
+            });
+            for (album, count) in &counts.albums {
+                albums.push(AlbumRow {
+                    artist,
+                    album,
+                    play_count: count.count,
+                    strategy: count.strategy,
+                    deciding_tracks: &count.deciding_tracks,
+                    ignored_tracks: &count.ignored_tracks,
+                });
+            }
+        }
+        let artists = Table::from_items(artists).map_err(to_sql)?;
+        let albums = Table::from_items(albums).map_err(to_sql)?;
+
+        let mut db = Connection::open(path)?;
+        let transaction = db.transaction()?;
+        tracks.write_sqlite(&transaction, "tracks", Some("id"))?;
+        playlists.write_sqlite(&transaction, "playlists", None)?;
+        items.write_sqlite(&transaction, "playlist_items", None)?;
+        artists.write_sqlite(&transaction, "artist_counts", Some("artist"))?;
+        albums.write_sqlite(&transaction, "album_counts", None)?;
+        transaction.commit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::{playlist::Playlist, track::Track};
+    use crate::test_fixtures::{library, track};
+
+    fn tracks() -> Library {
+        let mut library = library([
+            Track {
+                play_count: 3,
+                loved: true,
+                genre: Some("Rock, Pop".to_owned()),
+                ..track("1")
+            },
+            track("2"),
+        ]);
+        library.playlists.push(Playlist {
+            name: "Mix".to_owned(),
+            persistent_id: "PL".to_owned(),
+            track_ids: vec!["2".to_owned(), "1".to_owned()],
+            ..Default::default()
+        });
+        library
+    }
+
+    /// The values of `column` in each row.
+    fn column<'t>(table: &'t Table, column: &str) -> Vec<&'t Value> {
+        let index = table.columns.iter().position(|c| c == column).unwrap();
+        table.rows.iter().map(|row| &row[index]).collect()
+    }
+
+    #[test]
+    fn csv_round_trip() {
+        let directory = std::env::temp_dir().join(format!("an_csv_{}", std::process::id()));
+        tracks().csv_export(&directory).unwrap();
+        let mut reader = csv::Reader::from_path(directory.join("tracks.csv")).unwrap();
+        let headers: Vec<String> = reader
+            .headers()
+            .unwrap()
+            .iter()
+            .map(str::to_owned)
+            .collect();
+        let rows: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+        let mut items = csv::Reader::from_path(directory.join("playlist_items.csv")).unwrap();
+        let items: Vec<Vec<String>> = items
+            .records()
+            .map(|record| record.unwrap().iter().map(str::to_owned).collect())
+            .collect();
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(headers, tracks().track_table().unwrap().columns);
+        let value = |row: usize, column: &str| {
+            let index = headers.iter().position(|c| c == column).unwrap();
+            rows[row][index].to_owned()
+        };
+        assert_eq!(rows.len(), 2);
+        assert_eq!(value(0, "id"), "1");
+        assert_eq!(value(0, "genre"), "Rock, Pop");
+        assert_eq!(value(0, "play_count"), "3");
+        assert_eq!(value(0, "loved"), "true");
+        assert_eq!(value(0, "duration_ms"), "200000");
+        assert_eq!(value(1, "genre"), "");
+        assert_eq!(items, [["PL", "1", "2"], ["PL", "2", "1"]]);
+    }
+
+    #[test]
+    fn sqlite_round_trip() {
+        let table = tracks().track_table().unwrap();
+        let db = Connection::open_in_memory().unwrap();
+        table.write_sqlite(&db, "tracks", Some("id")).unwrap();
+
+        let mut stmt = db
+            .prepare("SELECT name, type, pk FROM pragma_table_info('tracks')")
+            .unwrap();
+        let columns: Vec<(String, String, bool)> = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        let names: Vec<&String> = columns.iter().map(|(name, _, _)| name).collect();
+        assert_eq!(names, table.columns.iter().collect::<Vec<_>>());
+        let find = |name: &str| columns.iter().find(|(n, _, _)| n == name).unwrap();
+        assert_eq!(find("id"), &("id".to_owned(), "TEXT".to_owned(), true));
+        assert_eq!(find("play_count").1, "INTEGER");
+        assert_eq!(find("loved").1, "INTEGER");
+
+        let (genre, play_count, loved, duration): (Option<String>, i64, bool, i64) = db
+            .query_row(
+                "SELECT genre, play_count, loved, duration_ms FROM tracks WHERE id = '1'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!(
+            (genre.as_deref(), play_count, loved, duration),
+            (Some("Rock, Pop"), 3, true, 200_000)
+        );
+        let genre: Option<String> = db
+            .query_row("SELECT genre FROM tracks WHERE id = '2'", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(genre, None);
+    }
+
+    #[test]
+    fn playlist_items_in_order() {
+        let (playlists, items) = tracks().playlist_tables().unwrap();
+        assert!(!playlists.columns.iter().any(|c| c == "track_ids"));
+        assert_eq!(
+            column(&items, "track_id"),
+            [&Value::from("2"), &Value::from("1")]
+        );
+        assert_eq!(
+            column(&items, "position"),
+            [&Value::from(1), &Value::from(2)]
+        );
+    }
+}
@@ -1,43 +1,122 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
 use std::time::Duration;
 
+use crate::xml_reader::tracks::decode_location;
+
 use super::TrackID;
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Track {
     pub album_artist: Option<String>,
     pub album_rating: Option<usize>,
+    pub album_rating_computed: bool,
     pub album_title: Option<String>,
     pub artist: Option<String>,
+    pub artwork_count: Option<usize>,
+    pub bit_rate: Option<usize>,
     pub bpm: Option<usize>,
     pub comments: Option<String>,
-    pub compiltion: bool,
+    pub compilation: bool,
     pub composer: Option<String>,
     pub date_added: DateTime<Utc>,
     pub date_modified: DateTime<Utc>,
+    pub disabled: bool,
     pub disc_count: Option<usize>,
     pub disc_number: Option<usize>,
+    #[serde(rename = "duration_ms", with = "super::json::duration_ms")]
     pub duration: Duration,
+    /// Keys which are not otherwise read, with their values.
+    pub extra: BTreeMap<String, String>,
     pub favourited: bool,
+    pub file_folder_count: Option<isize>,
     pub genre: Option<String>,
     pub grouping: Option<String>,
     pub id: TrackID,
+    pub kind: Option<String>,
+    pub library_folder_count: Option<isize>,
     pub location: String,
     pub loved: bool,
+    pub movement_count: Option<usize>,
     pub movement_number: Option<usize>,
     pub movement_title: Option<String>,
+    pub normalization: Option<usize>,
+    pub part_of_gapless_album: bool,
     pub persistent_id: String,
     pub play_count: usize,
     pub play_date: Option<DateTime<Utc>>,
     pub rating: usize,
-    pub release_data: Option<DateTime<Utc>>,
+    pub rating_computed: bool,
+    pub release_date: Option<DateTime<Utc>>,
+    pub sample_rate: Option<usize>,
     pub size: usize,
     pub skip_count: usize,
     pub skip_date: Option<DateTime<Utc>>,
+    pub sort_album: Option<String>,
+    pub sort_album_artist: Option<String>,
+    pub sort_artist: Option<String>,
+    pub sort_composer: Option<String>,
+    pub sort_name: Option<String>,
     pub title: Option<String>,
     pub total_tracks: Option<usize>,
     pub track_number: Option<usize>,
+    pub track_type: Option<String>,
+    pub volume_adjustment: Option<isize>,
     pub work: Option<String>,
     pub year: Option<usize>,
 }
+
+/// The album artist of compilations without one, as used by Navidrome.
+pub const VARIOUS_ARTISTS: &str = "Various Artists";
+
+impl Track {
+    /// The artist an album is grouped under: the album artist, "Various Artists" for a compilation, or the track artist.
+    pub fn album_artist_key(&self) -> Option<&str> {
+        match (&self.album_artist, self.compilation) {
+            (Some(album_artist), _) => Some(album_artist),
+            (None, true) => Some(VARIOUS_ARTISTS),
+            (None, false) => self.artist.as_deref(),
+        }
+    }
+
+    /// The time spent listening to the track, as play count × duration.
+    pub fn listening_time(&self) -> Duration {
+        self.duration * self.play_count as u32
+    }
+
+    /// The path of the file of the track, decoded from the location.
+    pub fn file_path(&self) -> Option<PathBuf> {
+        decode_location(&self.location).map(PathBuf::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::track;
+
+    #[test]
+    fn grouped_under_the_album_artist() {
+        let grouped = |album_artist: Option<&str>, compilation: bool| {
+            let track = Track {
+                artist: Some("Band feat. Guest".to_owned()),
+                album_artist: album_artist.map(str::to_owned),
+                compilation,
+                ..track("1")
+            };
+            track.album_artist_key().map(str::to_owned)
+        };
+        assert_eq!(grouped(Some("Band"), false).as_deref(), Some("Band"));
+        assert_eq!(grouped(Some("Band"), true).as_deref(), Some("Band"));
+        assert_eq!(grouped(None, true).as_deref(), Some(VARIOUS_ARTISTS));
+        assert_eq!(grouped(None, false).as_deref(), Some("Band feat. Guest"));
+        let unknown = Track {
+            artist: None,
+            ..track("1")
+        };
+        assert_eq!(unknown.album_artist_key(), None);
+    }
+}
@@ -0,0 +1,35 @@
+//! Tracks and libraries for unit tests, filled in with struct update syntax.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::structs::{track::Track, Library};
+
+/// A track by "Band" on "First", of 200 seconds, with its id in the title and persistent id.
+pub fn track(id: &str) -> Track {
+    Track {
+        id: id.to_owned(),
+        persistent_id: format!("P{id}"),
+        artist: Some("Band".to_owned()),
+        album_title: Some("First".to_owned()),
+        title: Some(format!("Song {id}")),
+        duration: Duration::from_secs(200),
+        kind: Some("MPEG audio file".to_owned()),
+        size: 1000,
+        ..Default::default()
+    }
+}
+
+pub fn library(tracks: impl IntoIterator<Item = Track>) -> Library {
+    let mut library = Library::default();
+    for track in tracks {
+        library.tracks.insert(track.id.clone(), track);
+    }
+    library
+}
+
+/// A date from RFC 3339 text, such as "2024-01-01T00:00:00Z".
+pub fn date(text: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(text).unwrap().to_utc()
+}
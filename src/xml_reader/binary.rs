@@ -0,0 +1,171 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use plist::{Dictionary, Value};
+
+use crate::structs::{playlist::Playlist, track::Track, Library};
+
+use super::{
+    err, playlists::set_playlist_field, tracks::set_track_field, Diagnostic, LibraryItem, ParseMode,
+};
+
+const BINARY_HEADER: &[u8; 8] = b"bplist00";
+
+/// Whether the file at `path` starts with the header of a binary property list.
+pub fn is_binary_plist(path: &Path) -> Result<bool, err::LibraryXmlReader> {
+    let mut header = [0; 8];
+    let mut file = File::open(path)?;
+    match file.read_exact(&mut header) {
+        Ok(_) => Ok(&header == BINARY_HEADER),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// A value as it would be read from an XML property list, or `None` for a dictionary or array.
+fn value_as_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(string) => Some(string.clone()),
+        Value::Integer(integer) => Some(integer.to_string()),
+        Value::Real(real) => Some(real.to_string()),
+        Value::Boolean(boolean) => Some(boolean.to_string()),
+        Value::Date(date) => Some(date.to_xml_format()),
+        // data is base64 in an XML property list, though here it's written as hex
+        Value::Data(bytes) => Some(bytes.iter().map(|b| format!("{b:02x}")).collect()),
+        _ => None,
+    }
+}
+
+fn unexpected(key: &str, value: &Value) -> err::LibraryXmlReader {
+    let value = match value {
+        Value::Array(_) => "an array".to_owned(),
+        Value::Dictionary(_) => "a dictionary".to_owned(),
+        other => format!("{other:?}"),
+    };
+    err::LibraryXmlReader::UnexpectedKV {
+        key: key.to_owned(),
+        value,
+    }
+}
+
+struct BinaryReader {
+    mode: ParseMode,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl BinaryReader {
+    fn problem(
+        &mut self,
+        key: &str,
+        error: err::LibraryXmlReader,
+    ) -> Result<(), err::LibraryXmlReader> {
+        self.mode.problem(&mut self.diagnostics, None, key, error)
+    }
+
+    fn get_track(&mut self, dict: Dictionary) -> Result<Track, err::LibraryXmlReader> {
+        let mut the_track = Track::default();
+        for (key, value) in dict {
+            match value_as_string(&value) {
+                Some(string) => {
+                    if let Err(e) = set_track_field(&mut the_track, &key, string) {
+                        self.problem(&key, e)?;
+                    }
+                }
+                None => self.problem(&key, unexpected(&key, &value))?,
+            }
+        }
+        Ok(the_track)
+    }
+
+    fn get_playlist(&mut self, dict: Dictionary) -> Result<Playlist, err::LibraryXmlReader> {
+        let mut the_playlist = Playlist::default();
+        for (key, value) in dict {
+            if key == "Playlist Items" {
+                let Value::Array(items) = value else {
+                    self.problem(&key, unexpected(&key, &value))?;
+                    continue;
+                };
+                for item in items {
+                    match item.as_dictionary().and_then(|d| d.get("Track ID")) {
+                        Some(id) => match value_as_string(id) {
+                            Some(id) => the_playlist.track_ids.push(id),
+                            None => self.problem("Track ID", unexpected("Track ID", id))?,
+                        },
+                        None => self.problem(&key, unexpected(&key, &item))?,
+                    }
+                }
+                continue;
+            }
+            match value_as_string(&value) {
+                Some(string) => {
+                    if let Err(e) = set_playlist_field(&mut the_playlist, &key, string) {
+                        self.problem(&key, e)?;
+                    }
+                }
+                None => self.problem(&key, unexpected(&key, &value))?,
+            }
+        }
+        Ok(the_playlist)
+    }
+}
+
+impl Library {
+    /// Reads a library from a binary property list, with the same structure as the XML file.
+    pub fn import_binary(
+        &mut self,
+        path: &Path,
+        mode: ParseMode,
+    ) -> Result<Vec<Diagnostic>, err::LibraryXmlReader> {
+        let root = Value::from_file(path)?;
+        let Value::Dictionary(library) = root else {
+            return Err(unexpected("plist", &root));
+        };
+
+        let mut reader = BinaryReader {
+            mode,
+            diagnostics: Vec::default(),
+        };
+
+        for (key, value) in library {
+            match (key.as_str(), value) {
+                ("Tracks", Value::Dictionary(tracks)) => {
+                    for (_id, track) in tracks {
+                        match track {
+                            Value::Dictionary(dict) => {
+                                let track = reader.get_track(dict)?;
+                                self.import_item(LibraryItem::Track(Box::new(track)));
+                            }
+                            other => reader.problem(&key, unexpected(&key, &other))?,
+                        }
+                    }
+                }
+                ("Playlists", Value::Array(playlists)) => {
+                    for playlist in playlists {
+                        match playlist {
+                            Value::Dictionary(dict) => {
+                                let playlist = reader.get_playlist(dict)?;
+                                self.import_item(LibraryItem::Playlist(playlist));
+                            }
+                            other => reader.problem(&key, unexpected(&key, &other))?,
+                        }
+                    }
+                }
+                ("Date", value) => {
+                    if let Some(date) =
+                        value_as_string(&value).and_then(|date| date.parse::<DateTime<Utc>>().ok())
+                    {
+                        self.import_item(LibraryItem::Date(date));
+                    }
+                }
+                (_, value) => match value_as_string(&value) {
+                    Some(value) => self.import_item(LibraryItem::Property { key, value }),
+                    None => reader.problem(&key, unexpected(&key, &value))?,
+                },
+            }
+        }
+
+        Ok(reader.diagnostics)
+    }
+}
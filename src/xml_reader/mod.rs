@@ -2,19 +2,75 @@ use std::fs::File;
 use std::io::BufReader;
 use std::path::Path;
 
-use chrono::DateTime;
 use xml::common::{Position, TextPosition};
 
 use xml::reader::{EventReader, ParserConfig2, XmlEvent};
 
 use crate::structs::Library;
 
+pub mod binary;
 pub mod playlists;
+pub mod stream;
 pub mod tracks;
 
+pub use stream::{LibraryItem, LibraryItems};
+
+/// How to handle a value or key which cannot be read.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Stop reading with an error.
+    #[default]
+    Strict,
+    /// Skip the value or key, recording a diagnostic.
+    Lenient,
+}
+
+impl ParseMode {
+    /// Returns `error` in strict mode, and otherwise adds `error` to `diagnostics`.
+    pub fn problem(
+        self,
+        diagnostics: &mut Vec<Diagnostic>,
+        position: Option<TextPosition>,
+        key: &str,
+        error: err::LibraryXmlReader,
+    ) -> Result<(), err::LibraryXmlReader> {
+        match self {
+            ParseMode::Strict => Err(err::LibraryXmlReader::Value {
+                position,
+                key: key.to_owned(),
+                error: Box::new(error),
+            }),
+            ParseMode::Lenient => {
+                match position {
+                    Some(position) => log::warn!("Skipped \"{key}\" at {position}: {error:?}"),
+                    None => log::warn!("Skipped \"{key}\": {error:?}"),
+                }
+                diagnostics.push(Diagnostic {
+                    position,
+                    key: key.to_owned(),
+                    error,
+                });
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A problem skipped over when reading in lenient mode.
+///
+/// There is no position for problems found when reading a binary property list.
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub position: Option<TextPosition>,
+    pub key: String,
+    pub error: err::LibraryXmlReader,
+}
+
 pub struct LibraryXmlReader {
     pub parser: EventReader<BufReader<File>>,
     pub event: XmlEvent,
+    pub mode: ParseMode,
+    pub diagnostics: Vec<Diagnostic>,
 }
 
 pub mod err {
@@ -67,11 +123,18 @@ pub mod err {
         },
         UnexpectedEvent {
             position: TextPosition,
-            event: XmlEvent,
+            event: Box<XmlEvent>,
         },
         ExpectedBooleanTag {
             position: TextPosition,
-        }
+        },
+        Plist(plist::Error),
+        /// A value which could not be read in strict mode, with where it was found.
+        Value {
+            position: Option<TextPosition>,
+            key: String,
+            error: Box<LibraryXmlReader>,
+        },
     }
 }
 
@@ -81,6 +144,12 @@ impl From<xml::reader::Error> for err::LibraryXmlReader {
     }
 }
 
+impl From<plist::Error> for err::LibraryXmlReader {
+    fn from(error: plist::Error) -> Self {
+        err::LibraryXmlReader::Plist(error)
+    }
+}
+
 impl From<std::io::Error> for err::LibraryXmlReader {
     fn from(error: std::io::Error) -> Self {
         err::LibraryXmlReader::Io { error }
@@ -112,9 +181,60 @@ impl LibraryXmlReader {
         Ok(LibraryXmlReader {
             parser,
             event: next,
+            mode: ParseMode::default(),
+            diagnostics: Vec::default(),
         })
     }
 
+    pub fn with_mode(mut self, mode: ParseMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Returns `error` in strict mode, and otherwise records `error` as a diagnostic.
+    pub fn problem(
+        &mut self,
+        position: TextPosition,
+        key: &str,
+        error: err::LibraryXmlReader,
+    ) -> Result<(), err::LibraryXmlReader> {
+        self.mode
+            .problem(&mut self.diagnostics, Some(position), key, error)
+    }
+
+    /// Whether the next element is a dict or array, rather than a single value.
+    pub fn value_is_collection(&self) -> bool {
+        match self.peek() {
+            XmlEvent::StartElement { name, .. } => {
+                matches!(name.local_name.as_str(), "dict" | "array")
+            }
+            _ => false,
+        }
+    }
+
+    /// Skips the next element, including any elements it contains.
+    pub fn skip_element(&mut self) -> Result<(), err::LibraryXmlReader> {
+        let mut depth = 0_usize;
+        loop {
+            match self.peek() {
+                XmlEvent::StartElement { .. } => depth += 1,
+                XmlEvent::EndElement { .. } => depth = depth.saturating_sub(1),
+                XmlEvent::EndDocument => {
+                    return Err(err::LibraryXmlReader::UnexpectedEvent {
+                        position: self.parser.position(),
+                        event: Box::new(XmlEvent::EndDocument),
+                    })
+                }
+                _ => {}
+            }
+            self.forward()?;
+            if depth == 0 {
+                break;
+            }
+        }
+        Ok(())
+    }
+
     pub fn peek(&self) -> &XmlEvent {
         &self.event
     }
@@ -248,59 +368,101 @@ impl Library {
         Ok(the_lib)
     }
 
+    /// Reads the library at `xml_path` in the given mode, along with any problems skipped over.
+    pub fn from_xml_with_mode(
+        xml_path: &Path,
+        mode: ParseMode,
+    ) -> Result<(Self, Vec<Diagnostic>), crate::xml_reader::err::LibraryXmlReader> {
+        let mut the_lib = Library::default();
+        let diagnostics = the_lib.import_xml_with_mode(xml_path, mode)?;
+        Ok((the_lib, diagnostics))
+    }
+
     pub fn import_xml(
         &mut self,
         path: &Path,
     ) -> Result<(), crate::xml_reader::err::LibraryXmlReader> {
-        let mut reader = LibraryXmlReader::new(path)?;
-        // skip until library dictionary
-        loop {
-            if let Ok(xml::reader::XmlEvent::StartElement { name, .. }) = reader.forward() {
-                if name.local_name == "dict" {
-                    break;
-                }
-            }
+        self.import_xml_with_mode(path, ParseMode::Strict)?;
+        Ok(())
+    }
+
+    /// Reads the library at `path`, collecting the items of [LibraryXmlReader::items].
+    ///
+    /// A binary property list is detected from the header of the file, and read with [Library::import_binary].
+    pub fn import_xml_with_mode(
+        &mut self,
+        path: &Path,
+        mode: ParseMode,
+    ) -> Result<Vec<Diagnostic>, crate::xml_reader::err::LibraryXmlReader> {
+        if binary::is_binary_plist(path)? {
+            return self.import_binary(path, mode);
         }
-        reader.eat_start("dict")?;
-        loop {
-            match reader.peek() {
-                xml::reader::XmlEvent::StartElement { name, .. } => {
-                    if name.local_name == "key" {
-                        let key = reader.element_as_string(Some("key"))?;
-                        match key.as_str() {
-                            "Tracks" => self.import_tracks(&mut reader)?,
-                            "Playlists" => self.import_playlists(&mut reader)?,
-                            "Date" => {
-                                let value = reader.element_as_string(None)?;
-                                if let Ok(date) = value.parse::<DateTime<chrono::Utc>>() {
-                                    self.date = date;
-                                }
-                            }
-                            _ => {
-                                let value = reader.element_as_string(None)?;
-                                log::debug!(
-                                    "Ignored top level Apple Music key/value pair: {key} | {value}"
-                                );
-                            }
-                        }
-                    } else {
-                        return Err(err::LibraryXmlReader::UnexpectedElement {
-                            position: reader.parser.position(),
-                        });
-                    }
-                }
-                xml::reader::XmlEvent::EndElement { .. } => {
-                    reader.eat_end("dict")?;
-                    break;
-                }
+
+        let mut items = LibraryXmlReader::new(path)?.with_mode(mode).items();
+        for item in items.by_ref() {
+            self.import_item(item?);
+        }
+        Ok(items.take_diagnostics())
+    }
+
+    pub fn import_item(&mut self, item: LibraryItem) {
+        match item {
+            LibraryItem::Date(date) => self.date = date,
+            LibraryItem::Property { key, value } => match key.as_str() {
+                "Application Version" => self.application_version = Some(value),
+                "Music Folder" => self.music_folder = Some(value),
+                "Library Persistent ID" => self.library_persistent_id = Some(value),
                 _ => {
-                    return Err(err::LibraryXmlReader::UnexpectedEvent {
-                        position: reader.parser.position(),
-                        event: reader.peek().to_owned(),
-                    });
+                    log::debug!("Ignored top level Apple Music key/value pair: {key} | {value}")
                 }
+            },
+            LibraryItem::Track(track) => {
+                self.tracks.insert(track.id.clone(), *track);
             }
+            LibraryItem::Playlist(playlist) => self.playlists.push(playlist),
         }
-        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bad_int() -> err::LibraryXmlReader {
+        "ten".parse::<usize>().unwrap_err().into()
+    }
+
+    #[test]
+    fn strict_problems_keep_where_they_were_found() {
+        let mut diagnostics = vec![];
+        let position = TextPosition {
+            row: 32,
+            column: 24,
+        };
+        let error = ParseMode::Strict
+            .problem(&mut diagnostics, Some(position), "Play Count", bad_int())
+            .unwrap_err();
+        let err::LibraryXmlReader::Value {
+            position: found,
+            key,
+            error,
+        } = error
+        else {
+            panic!("expected the error with its position, found {error:?}");
+        };
+        assert_eq!(found, Some(position));
+        assert_eq!(key, "Play Count");
+        assert!(matches!(*error, err::LibraryXmlReader::ParseInt(_)));
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn lenient_problems_are_recorded() {
+        let mut diagnostics = vec![];
+        ParseMode::Lenient
+            .problem(&mut diagnostics, None, "Play Count", bad_int())
+            .unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].key, "Play Count");
     }
 }
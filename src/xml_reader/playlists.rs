@@ -24,37 +24,20 @@ pub fn get_playlist(
                         let key = reader.element_as_string(Some("key"))?;
                         if key == "Playlist Items" {
                             the_playlist.track_ids = playlist_ids(reader)?;
+                        } else if reader.value_is_collection() {
+                            reader.problem(
+                                the_position,
+                                &key,
+                                err::LibraryXmlReader::UnexpectedKey {
+                                    position: the_position,
+                                    key: key.clone(),
+                                },
+                            )?;
+                            reader.skip_element()?;
                         } else {
                             let value = reader.element_as_string(None)?;
-                            match key.as_str() {
-                                "Name" => the_playlist.name = value,
-                                "Description" => the_playlist.description = value,
-                                "Playlist Persistent ID" => the_playlist.persistent_id = value,
-                                "Parent Persistent ID" => the_playlist.parent_persistent_id = value,
-                                "Folder" => match value.as_str() {
-                                    "true" => the_playlist.folder = true,
-                                    "false" => the_playlist.folder = false,
-                                    _ => {
-                                        return Err(err::LibraryXmlReader::ExpectedBooleanTag {
-                                            position: reader.parser.position(),
-                                        })
-                                    }
-                                },
-                                "Master" => {}
-                                "Playlist ID" => {}
-                                "Smart Info" => {}
-                                "Smart Criteria" => {}
-                                "Distinguished Kind" => {}
-                                "Music" => {}
-                                "Visible" => {}
-                                "All Items" => {}
-                                _ => {
-                                    log::error!("Playlist parsing failed");
-                                    return Err(err::LibraryXmlReader::UnexpectedKey {
-                                        position: the_position,
-                                        key: the_key,
-                                    });
-                                }
+                            if let Err(e) = set_playlist_field(&mut the_playlist, &key, value) {
+                                reader.problem(the_position, &key, e)?;
                             }
                         }
                     }
@@ -76,6 +59,55 @@ pub fn get_playlist(
     Ok(the_playlist)
 }
 
+pub fn set_playlist_field(
+    the_playlist: &mut Playlist,
+    key: &str,
+    value: String,
+) -> Result<(), xml_reader::err::LibraryXmlReader> {
+    match key {
+        "Name" => the_playlist.name = value,
+        "Description" => the_playlist.description = value,
+        "Playlist Persistent ID" => the_playlist.persistent_id = value,
+        "Parent Persistent ID" => the_playlist.parent_persistent_id = value,
+        "Folder" => match value.as_str() {
+            "true" => the_playlist.folder = true,
+            "false" => the_playlist.folder = false,
+            _ => {
+                return Err(err::LibraryXmlReader::UnexpectedKV {
+                    key: key.to_owned(),
+                    value,
+                })
+            }
+        },
+        "Master" => {}
+        "Playlist ID" => {}
+        "Smart Info" => {}
+        "Smart Criteria" => {}
+        "Distinguished Kind" => {}
+        "Music" => {}
+        "Visible" => {}
+        "All Items" => {}
+        // older versions of iTunes
+        "Audiobooks" => {}
+        "Books" => {}
+        "Genius Track ID" => {}
+        "iTunesU" => {}
+        "Movies" => {}
+        "Party Shuffle" => {}
+        "Podcasts" => {}
+        "Purchased Music" => {}
+        "TV Shows" => {}
+        "Videos" => {}
+        _ => {
+            return Err(err::LibraryXmlReader::UnexpectedKV {
+                key: key.to_owned(),
+                value,
+            })
+        }
+    }
+    Ok(())
+}
+
 pub fn playlist_ids(
     reader: &mut LibraryXmlReader,
 ) -> Result<Vec<TrackID>, xml_reader::err::LibraryXmlReader> {
@@ -83,14 +115,12 @@ pub fn playlist_ids(
     reader.eat_start("array")?;
     loop {
         match reader.peek() {
-            XmlEvent::StartElement { name, .. } => {
-                if name.local_name == "dict" {
-                    reader.eat_start("dict")?;
-                    let _key = reader.element_as_string(Some("key"));
-                    let id = reader.element_as_string(Some("integer"))?;
-                    ids.push(id);
-                    reader.eat_end("dict")?;
-                }
+            XmlEvent::StartElement { name, .. } if name.local_name == "dict" => {
+                reader.eat_start("dict")?;
+                let _key = reader.element_as_string(Some("key"));
+                let id = reader.element_as_string(Some("integer"))?;
+                ids.push(id);
+                reader.eat_end("dict")?;
             }
             XmlEvent::EndElement { .. } => {
                 reader.eat_end("array")?;
@@ -101,37 +131,3 @@ pub fn playlist_ids(
     }
     Ok(ids)
 }
-
-impl Library {
-    pub fn import_playlists(
-        &mut self,
-        reader: &mut LibraryXmlReader,
-    ) -> Result<(), xml_reader::err::LibraryXmlReader> {
-        reader.eat_start("array")?;
-        // process each track
-        loop {
-            match reader.peek() {
-                XmlEvent::StartElement { name, .. } => {
-                    //
-                    match name.local_name.as_str() {
-                        "dict" => {
-                            self.playlists.push(get_playlist(reader)?);
-                        }
-                        _ => {
-                            return Err(err::LibraryXmlReader::UnexpectedKey {
-                                position: reader.parser.position(),
-                                key: name.local_name.to_owned(),
-                            })
-                        }
-                    }
-                }
-                XmlEvent::EndElement { .. } => {
-                    reader.eat_end("array")?;
-                    break;
-                }
-                _ => {}
-            }
-        }
-        Ok(())
-    }
-}
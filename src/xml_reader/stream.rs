@@ -0,0 +1,212 @@
+use chrono::{DateTime, Utc};
+use xml::common::Position;
+use xml::reader::XmlEvent;
+
+use crate::structs::{playlist::Playlist, track::Track};
+
+use super::{err, playlists::get_playlist, tracks::get_track, Diagnostic, LibraryXmlReader};
+
+/// Something read from the library, in the order it appears in the XML file.
+#[derive(Debug)]
+pub enum LibraryItem {
+    Date(DateTime<Utc>),
+    /// Some other top level key/value pair.
+    Property {
+        key: String,
+        value: String,
+    },
+    Track(Box<Track>),
+    Playlist(Playlist),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Section {
+    Start,
+    Library,
+    Tracks,
+    Playlists,
+    Done,
+}
+
+/// An iterator over the items of a library, reading only as much of the XML file as is needed for the next item.
+pub struct LibraryItems {
+    reader: LibraryXmlReader,
+    section: Section,
+}
+
+impl LibraryXmlReader {
+    pub fn items(self) -> LibraryItems {
+        LibraryItems {
+            reader: self,
+            section: Section::Start,
+        }
+    }
+}
+
+impl LibraryItems {
+    /// Problems skipped over so far, when reading in lenient mode.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.reader.diagnostics
+    }
+
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.reader.diagnostics)
+    }
+
+    /// The tracks of the library, skipping all other items.
+    pub fn tracks(self) -> impl Iterator<Item = Result<Track, err::LibraryXmlReader>> {
+        self.filter_map(|item| match item {
+            Ok(LibraryItem::Track(track)) => Some(Ok(*track)),
+            Ok(_) => None,
+            Err(e) => Some(Err(e)),
+        })
+    }
+
+    /// The playlists of the library, skipping all other items.
+    pub fn playlists(self) -> impl Iterator<Item = Result<Playlist, err::LibraryXmlReader>> {
+        self.filter_map(|item| match item {
+            Ok(LibraryItem::Playlist(playlist)) => Some(Ok(playlist)),
+            Ok(_) => None,
+            Err(e) => Some(Err(e)),
+        })
+    }
+
+    fn next_item(&mut self) -> Result<Option<LibraryItem>, err::LibraryXmlReader> {
+        let reader = &mut self.reader;
+        loop {
+            match self.section {
+                Section::Start => {
+                    // skip until library dictionary
+                    loop {
+                        match reader.forward()? {
+                            XmlEvent::StartElement { name, .. } if name.local_name == "dict" => {
+                                break
+                            }
+                            XmlEvent::EndDocument => {
+                                return Err(err::LibraryXmlReader::UnexpectedEvent {
+                                    position: reader.parser.position(),
+                                    event: Box::new(XmlEvent::EndDocument),
+                                })
+                            }
+                            _ => {}
+                        }
+                    }
+                    reader.eat_start("dict")?;
+                    self.section = Section::Library;
+                }
+
+                Section::Library => match reader.peek() {
+                    XmlEvent::StartElement { name, .. } => {
+                        if name.local_name != "key" {
+                            return Err(err::LibraryXmlReader::UnexpectedElement {
+                                position: reader.parser.position(),
+                            });
+                        }
+                        let key = reader.element_as_string(Some("key"))?;
+                        match key.as_str() {
+                            "Tracks" => {
+                                reader.eat_start("dict")?;
+                                self.section = Section::Tracks;
+                            }
+                            "Playlists" => {
+                                reader.eat_start("array")?;
+                                self.section = Section::Playlists;
+                            }
+                            "Date" => {
+                                let value = reader.element_as_string(None)?;
+                                if let Ok(date) = value.parse::<DateTime<Utc>>() {
+                                    return Ok(Some(LibraryItem::Date(date)));
+                                }
+                            }
+                            _ => {
+                                let value = reader.element_as_string(None)?;
+                                return Ok(Some(LibraryItem::Property { key, value }));
+                            }
+                        }
+                    }
+                    XmlEvent::EndElement { .. } => {
+                        reader.eat_end("dict")?;
+                        self.section = Section::Done;
+                    }
+                    _ => {
+                        return Err(err::LibraryXmlReader::UnexpectedEvent {
+                            position: reader.parser.position(),
+                            event: Box::new(reader.peek().to_owned()),
+                        });
+                    }
+                },
+
+                Section::Tracks => match reader.peek() {
+                    XmlEvent::StartElement { name, .. } => match name.local_name.as_str() {
+                        "key" => {
+                            let id = reader.element_as_string(Some("key"))?;
+                            let position = reader.parser.position();
+                            let track = get_track(reader)?;
+                            if id != track.id {
+                                // the track is kept under its own id, in lenient mode
+                                reader.problem(
+                                    position,
+                                    "Track ID",
+                                    err::LibraryXmlReader::UnexpectedKV {
+                                        key: id,
+                                        value: track.id.clone(),
+                                    },
+                                )?;
+                            }
+                            return Ok(Some(LibraryItem::Track(Box::new(track))));
+                        }
+                        _ => {
+                            return Err(err::LibraryXmlReader::UnexpectedKey {
+                                position: reader.parser.position(),
+                                key: name.local_name.to_owned(),
+                            });
+                        }
+                    },
+                    XmlEvent::EndElement { .. } => {
+                        reader.eat_end("dict")?;
+                        self.section = Section::Library;
+                    }
+                    _ => {
+                        reader.forward()?;
+                    }
+                },
+
+                Section::Playlists => match reader.peek() {
+                    XmlEvent::StartElement { name, .. } => match name.local_name.as_str() {
+                        "dict" => return Ok(Some(LibraryItem::Playlist(get_playlist(reader)?))),
+                        _ => {
+                            return Err(err::LibraryXmlReader::UnexpectedKey {
+                                position: reader.parser.position(),
+                                key: name.local_name.to_owned(),
+                            })
+                        }
+                    },
+                    XmlEvent::EndElement { .. } => {
+                        reader.eat_end("array")?;
+                        self.section = Section::Library;
+                    }
+                    _ => {
+                        reader.forward()?;
+                    }
+                },
+
+                Section::Done => return Ok(None),
+            }
+        }
+    }
+}
+
+impl Iterator for LibraryItems {
+    type Item = Result<LibraryItem, err::LibraryXmlReader>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_item() {
+            Ok(item) => item.map(Ok),
+            Err(e) => {
+                // the position of the reader is unknown after an error, so nothing more is read
+                self.section = Section::Done;
+                Some(Err(e))
+            }
+        }
+    }
+}
@@ -4,148 +4,188 @@ use std::time::Duration;
 use xml::{common::Position, reader::XmlEvent};
 
 use crate::{
-    structs::{track::Track, Library},
+    structs::track::Track,
     xml_reader::{
         self,
         err::{self},
     },
 };
 
-use super::LibraryXmlReader;
+/// The path of a file from a location in the library, or `None` if the location is not a file URL.
+///
+/// Handles locations written by Apple Music and by iTunes for macOS and Windows, for example:
+/// - `file:///Users/me/Music/a%20b.mp3` as `/Users/me/Music/a b.mp3`
+/// - `file://localhost/C:/Music/a%20b.mp3` as `C:/Music/a b.mp3`
+/// - `file://server/share/a.mp3` as `//server/share/a.mp3`
+pub fn decode_location(location: &str) -> Option<String> {
+    let rest = location.strip_prefix("file://")?;
+    let path = match rest.strip_prefix("localhost") {
+        Some(local) => local.to_owned(),
+        None if rest.starts_with('/') => rest.to_owned(),
+        None => format!("//{rest}"),
+    };
+    let decoded = urlencoding::decode(&path).ok()?.into_owned();
 
-#[rustfmt::skip]
-pub fn get_track(reader: &mut xml_reader::LibraryXmlReader) -> Result<Track, err::LibraryXmlReader> {
+    // a windows drive follows the slash of the url, e.g. /C:/
+    let bytes = decoded.as_bytes();
+    if bytes.len() >= 3 && bytes[0] == b'/' && bytes[1].is_ascii_alphabetic() && bytes[2] == b':' {
+        return Some(decoded[1..].to_owned());
+    }
+    Some(decoded)
+}
+
+pub fn get_track(
+    reader: &mut xml_reader::LibraryXmlReader,
+) -> Result<Track, err::LibraryXmlReader> {
     let _ = reader.forward();
     let mut the_track = Track::default();
     loop {
         match reader.peek() {
             XmlEvent::StartElement { .. } => {
                 let key = reader.element_as_string(Some("key"))?;
+                let position = reader.parser.position();
+                if reader.value_is_collection() {
+                    reader.problem(
+                        position,
+                        &key,
+                        err::LibraryXmlReader::UnexpectedKey {
+                            position,
+                            key: key.clone(),
+                        },
+                    )?;
+                    reader.skip_element()?;
+                    continue;
+                }
                 let value = reader.element_as_string(None)?;
-                match key.as_str() {
-
-                    "Album Artist" => the_track.album_artist = Some(value),
-                    "Album Rating Computed" => {}
-                    "Album Rating" => the_track.album_rating = Some(value.parse::<usize>()?),
-                    "Album" => the_track.album_title = Some(value),
-                    "Artist" => the_track.artist = Some(value),
-                    "Artwork Count" => {}
-                    "BPM" => the_track.bpm = Some(value.parse::<usize>()?),
-                    "Bit Rate" => {}
-                    "Comments" => the_track.comments = Some(value),
-                    "Compilation" => the_track.compiltion = true,
-                    "Composer" => the_track.composer = Some(value),
-                    "Date Added" => the_track.date_added = value.parse::<DateTime<Utc>>()?,
-                    "Date Modified" => the_track.date_modified = value.parse::<DateTime<Utc>>()?,
-                    "Disabled" => {}
-                    "Disc Count" => the_track.disc_count = Some(value.parse::<usize>()?),
-                    "Disc Number" => the_track.disc_number = Some(value.parse::<usize>()?),
-                    "Favorited" => the_track.favourited = true,
-                    "File Folder Count" => {}
-                    "Genre" => the_track.genre = Some(value),
-                    "Grouping" => the_track.grouping = Some(value),
-                    "Kind" => {}
-                    "Library Folder Count" => {}
-                    "Location" => the_track.location = value,
-                    "Loved" => the_track.loved = true,
-                    "Movement Count" => {}
-                    "Movement Name" => the_track.movement_title = Some(value),
-                    "Movement Number" => the_track.movement_number = Some(value.parse::<usize>()?),
-                    "Name" => the_track.title = Some(value),
-                    "Normalization" => {}
-                    "Part Of Gapless Album" => {}
-                    "Persistent ID" => the_track.persistent_id = value,
-                    "Play Count" => the_track.play_count = value.parse::<usize>()?,
-                    "Play Date UTC" => the_track.play_date = Some(value.parse::<DateTime<Utc>>()?),
-                    "Play Date" => {} // use utc variant
-                    "Rating Computed" => {}
-                    "Rating" => the_track.rating = value.parse::<usize>()?,
-                    "Release Date" => the_track.release_data = Some(value.parse::<DateTime<Utc>>()?),
-                    "Sample Rate" => {}
-                    "Size" => the_track.size = value.parse::<usize>()?,
-                    "Skip Count" => the_track.skip_count = value.parse::<usize>()?,
-                    "Skip Date" => the_track.skip_date = Some(value.parse::<DateTime<Utc>>()?),
-                    "Sort Album Artist" => {}
-                    "Sort Album" => {}
-                    "Sort Artist" => {}
-                    "Sort Composer" => {}
-                    "Sort Name" => {}
-                    "Total Time" => the_track.duration = Duration::from_millis(value.parse::<u64>()?),
-                    "Track Count" => the_track.total_tracks = Some(value.parse::<usize>()?),
-                    "Track ID" => the_track.id = value,
-                    "Track Number" => the_track.track_number = Some(value.parse::<usize>()?),
-                    "Track Type" => {}
-                    "Volume Adjustment" => {}
-                    "Work" => the_track.work = Some(value),
-                    "Year" => the_track.year = Some(value.parse::<usize>()?),
-
-                    // missed something?
-                    _ => {
-                        let title = match &the_track.title {
-                            Some(found) => found,
-                            None => "[No title]"
-                        };
-                        let artist = match &the_track.artist {
-                            Some(found) => found,
-                            None => "[No artist]",
-                        };
-                        println!("Unexpected key:\n\t\"{key}\" with value\n\t\"{value}\"\nwhen reading{title} by {artist}.");
-                    }
+                if let Err(e) = set_track_field(&mut the_track, &key, value) {
+                    reader.problem(position, &key, e)?;
                 }
             }
-            XmlEvent::EndElement { name } => {
-                match name.local_name.as_str() {
-                    "dict" => {
-                        let _ = reader.forward();
-                        break;
-                    }
-                    _ => {
-                        return Err(err::LibraryXmlReader::UnexpectedKey {
-                            position: reader.parser.position(),
-                            key: name.local_name.to_owned(),
-                        })
-                    }
+            XmlEvent::EndElement { name } => match name.local_name.as_str() {
+                "dict" => {
+                    let _ = reader.forward();
+                    break;
                 }
-            }
+                _ => {
+                    return Err(err::LibraryXmlReader::UnexpectedKey {
+                        position: reader.parser.position(),
+                        key: name.local_name.to_owned(),
+                    })
+                }
+            },
             _ => {}
         }
     }
     Ok(the_track)
 }
 
-impl Library {
-    pub fn import_tracks(
-        &mut self,
-        reader: &mut LibraryXmlReader,
-    ) -> Result<(), xml_reader::err::LibraryXmlReader> {
-        // process each track
-        reader.eat_start("dict")?;
-        loop {
-            match reader.peek() {
-                XmlEvent::StartElement { name, .. } => {
-                    //
-                    match name.local_name.as_str() {
-                        "key" => {
-                            let id = reader.element_as_string(Some("key"))?;
-                            let track = get_track(reader)?;
-                            assert_eq!(id, track.id);
-                            self.tracks.insert(id, track);
-                        }
-                        _ => {
-                            return Err(err::LibraryXmlReader::UnexpectedKey {
-                                position: reader.parser.position(),
-                                key: name.local_name.to_owned(),
-                            });
-                        }
-                    }
-                }
-                XmlEvent::EndElement { .. } => {
-                    reader.eat_end("dict")?;
-                    break;
-                }
-                _ => {}
-            }
+#[rustfmt::skip]
+pub fn set_track_field(the_track: &mut Track, key: &str, value: String) -> Result<(), err::LibraryXmlReader> {
+    match key {
+
+        "Album Artist" => the_track.album_artist = Some(value),
+        "Album Rating Computed" => the_track.album_rating_computed = value == "true",
+        "Album Rating" => the_track.album_rating = Some(value.parse::<usize>()?),
+        "Album" => the_track.album_title = Some(value),
+        "Artist" => the_track.artist = Some(value),
+        "Artwork Count" => the_track.artwork_count = Some(value.parse::<usize>()?),
+        "BPM" => the_track.bpm = Some(value.parse::<usize>()?),
+        "Bit Rate" => the_track.bit_rate = Some(value.parse::<usize>()?),
+        "Comments" => the_track.comments = Some(value),
+        "Compilation" => the_track.compilation = true,
+        "Composer" => the_track.composer = Some(value),
+        "Date Added" => the_track.date_added = value.parse::<DateTime<Utc>>()?,
+        "Date Modified" => the_track.date_modified = value.parse::<DateTime<Utc>>()?,
+        "Disabled" => the_track.disabled = value == "true",
+        "Disc Count" => the_track.disc_count = Some(value.parse::<usize>()?),
+        "Disc Number" => the_track.disc_number = Some(value.parse::<usize>()?),
+        "Favorited" => the_track.favourited = true,
+        "File Folder Count" => the_track.file_folder_count = Some(value.parse::<isize>()?),
+        "Genre" => the_track.genre = Some(value),
+        "Grouping" => the_track.grouping = Some(value),
+        "Kind" => the_track.kind = Some(value),
+        "Library Folder Count" => the_track.library_folder_count = Some(value.parse::<isize>()?),
+        "Location" => the_track.location = value,
+        "Loved" => the_track.loved = true,
+        "Movement Count" => the_track.movement_count = Some(value.parse::<usize>()?),
+        "Movement Name" => the_track.movement_title = Some(value),
+        "Movement Number" => the_track.movement_number = Some(value.parse::<usize>()?),
+        "Name" => the_track.title = Some(value),
+        "Normalization" => the_track.normalization = Some(value.parse::<usize>()?),
+        "Part Of Gapless Album" => the_track.part_of_gapless_album = value == "true",
+        "Persistent ID" => the_track.persistent_id = value,
+        "Play Count" => the_track.play_count = value.parse::<usize>()?,
+        "Play Date UTC" => the_track.play_date = Some(value.parse::<DateTime<Utc>>()?),
+        "Play Date" => {} // use utc variant
+        "Rating Computed" => the_track.rating_computed = value == "true",
+        "Rating" => the_track.rating = value.parse::<usize>()?,
+        "Release Date" => the_track.release_date = Some(value.parse::<DateTime<Utc>>()?),
+        "Sample Rate" => the_track.sample_rate = Some(value.parse::<usize>()?),
+        "Size" => the_track.size = value.parse::<usize>()?,
+        "Skip Count" => the_track.skip_count = value.parse::<usize>()?,
+        "Skip Date" => the_track.skip_date = Some(value.parse::<DateTime<Utc>>()?),
+        "Sort Album Artist" => the_track.sort_album_artist = Some(value),
+        "Sort Album" => the_track.sort_album = Some(value),
+        "Sort Artist" => the_track.sort_artist = Some(value),
+        "Sort Composer" => the_track.sort_composer = Some(value),
+        "Sort Name" => the_track.sort_name = Some(value),
+        "Total Time" => the_track.duration = Duration::from_millis(value.parse::<u64>()?),
+        "Track Count" => the_track.total_tracks = Some(value.parse::<usize>()?),
+        "Track ID" => the_track.id = value,
+        "Track Number" => the_track.track_number = Some(value.parse::<usize>()?),
+        "Track Type" => the_track.track_type = Some(value),
+        "Volume Adjustment" => the_track.volume_adjustment = Some(value.parse::<isize>()?),
+        "Work" => the_track.work = Some(value),
+        "Year" => the_track.year = Some(value.parse::<usize>()?),
+
+        // missed something?
+        _ => {
+            log::debug!("Kept unexpected track key \"{key}\" with value \"{value}\"");
+            the_track.extra.insert(key.to_owned(), value);
         }
-        Ok(())
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::decode_location;
+
+    #[test]
+    fn decodes_mac_locations() {
+        assert_eq!(
+            decode_location("file:///Users/me/Music/a%20b.mp3").as_deref(),
+            Some("/Users/me/Music/a b.mp3")
+        );
+        assert_eq!(
+            decode_location("file://localhost/Users/me/Music/%C3%A9t%C3%A9.m4a").as_deref(),
+            Some("/Users/me/Music/été.m4a")
+        );
+    }
+
+    #[test]
+    fn decodes_windows_drives() {
+        assert_eq!(
+            decode_location("file://localhost/C:/Music/a%20b.mp3").as_deref(),
+            Some("C:/Music/a b.mp3")
+        );
+        assert_eq!(
+            decode_location("file:///D:/Music/a.mp3").as_deref(),
+            Some("D:/Music/a.mp3")
+        );
+    }
+
+    #[test]
+    fn decodes_unc_shares() {
+        assert_eq!(
+            decode_location("file://server/share/Music/a%20b.mp3").as_deref(),
+            Some("//server/share/Music/a b.mp3")
+        );
+    }
+
+    #[test]
+    fn ignores_other_urls() {
+        assert_eq!(decode_location("http://example.com/a.mp3"), None);
+        assert_eq!(decode_location(""), None);
     }
 }
@@ -0,0 +1,249 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::time::SystemTime;
+
+use chrono::{DateTime, Timelike, Utc};
+use plist::{Dictionary, Value};
+
+use crate::structs::{playlist::Playlist, track::Track, Library, TrackID};
+
+/// Dates are written to the second, as by Apple Music.
+fn date(date: DateTime<Utc>) -> Value {
+    let date = date.with_nanosecond(0).unwrap_or(date);
+    Value::Date(SystemTime::from(date).into())
+}
+
+fn integer(value: usize) -> Value {
+    Value::Integer((value as u64).into())
+}
+
+/// The keys of a track as written by Apple Music, for those fields which are set, with `id` as its Track ID.
+pub fn track_dictionary(track: &Track, id: i64) -> Dictionary {
+    let mut dict = Dictionary::new();
+    let mut string = |key: &str, value: &Option<String>| {
+        if let Some(value) = value {
+            dict.insert(key.to_owned(), Value::String(value.clone()));
+        }
+    };
+    string("Name", &track.title);
+    string("Artist", &track.artist);
+    string("Album Artist", &track.album_artist);
+    string("Composer", &track.composer);
+    string("Album", &track.album_title);
+    string("Grouping", &track.grouping);
+    string("Work", &track.work);
+    string("Movement Name", &track.movement_title);
+    string("Genre", &track.genre);
+    string("Kind", &track.kind);
+    string("Comments", &track.comments);
+    string("Sort Name", &track.sort_name);
+    string("Sort Artist", &track.sort_artist);
+    string("Sort Album Artist", &track.sort_album_artist);
+    string("Sort Album", &track.sort_album);
+    string("Sort Composer", &track.sort_composer);
+    string("Track Type", &track.track_type);
+
+    let mut number = |key: &str, value: Option<usize>| {
+        if let Some(value) = value {
+            dict.insert(key.to_owned(), integer(value));
+        }
+    };
+    number("Disc Number", track.disc_number);
+    number("Disc Count", track.disc_count);
+    number("Track Number", track.track_number);
+    number("Track Count", track.total_tracks);
+    number("Movement Number", track.movement_number);
+    number("Movement Count", track.movement_count);
+    number("Year", track.year);
+    number("BPM", track.bpm);
+    number("Bit Rate", track.bit_rate);
+    number("Sample Rate", track.sample_rate);
+    number("Album Rating", track.album_rating);
+    number("Artwork Count", track.artwork_count);
+    number("Normalization", track.normalization);
+
+    let mut signed = |key: &str, value: Option<isize>| {
+        if let Some(value) = value {
+            dict.insert(key.to_owned(), Value::Integer((value as i64).into()));
+        }
+    };
+    signed("Volume Adjustment", track.volume_adjustment);
+    signed("File Folder Count", track.file_folder_count);
+    signed("Library Folder Count", track.library_folder_count);
+
+    dict.insert("Track ID".to_owned(), Value::Integer(id.into()));
+    dict.insert("Size".to_owned(), integer(track.size));
+    dict.insert(
+        "Total Time".to_owned(),
+        integer(track.duration.as_millis() as usize),
+    );
+    dict.insert("Date Modified".to_owned(), date(track.date_modified));
+    dict.insert("Date Added".to_owned(), date(track.date_added));
+    if track.play_count > 0 {
+        dict.insert("Play Count".to_owned(), integer(track.play_count));
+    }
+    if let Some(play_date) = track.play_date {
+        dict.insert("Play Date UTC".to_owned(), date(play_date));
+    }
+    if track.skip_count > 0 {
+        dict.insert("Skip Count".to_owned(), integer(track.skip_count));
+    }
+    if let Some(skip_date) = track.skip_date {
+        dict.insert("Skip Date".to_owned(), date(skip_date));
+    }
+    if let Some(release_date) = track.release_date {
+        dict.insert("Release Date".to_owned(), date(release_date));
+    }
+    if track.rating > 0 {
+        dict.insert("Rating".to_owned(), integer(track.rating));
+    }
+    let mut flag = |key: &str, value: bool| {
+        if value {
+            dict.insert(key.to_owned(), Value::Boolean(true));
+        }
+    };
+    flag("Loved", track.loved);
+    flag("Favorited", track.favourited);
+    flag("Compilation", track.compilation);
+    flag("Disabled", track.disabled);
+    flag("Album Rating Computed", track.album_rating_computed);
+    flag("Rating Computed", track.rating_computed);
+    flag("Part Of Gapless Album", track.part_of_gapless_album);
+    for (key, value) in &track.extra {
+        dict.insert(key.clone(), Value::String(value.clone()));
+    }
+    dict.insert(
+        "Persistent ID".to_owned(),
+        Value::String(track.persistent_id.clone()),
+    );
+    dict.insert("Location".to_owned(), Value::String(track.location.clone()));
+    dict
+}
+
+/// Track ids are integers in Apple Music, so each track keeps its id if it is one,
+/// and tracks with other ids, such as those from Navidrome, are numbered after the largest.
+fn track_ids<'t>(tracks: impl Iterator<Item = &'t Track>) -> HashMap<TrackID, i64> {
+    let mut tracks: Vec<&Track> = tracks.collect();
+    tracks.sort_by(|a, b| a.id.cmp(&b.id));
+    let mut ids = HashMap::with_capacity(tracks.len());
+    let mut used = HashSet::with_capacity(tracks.len());
+    let mut unnumbered = vec![];
+    for track in tracks {
+        match track.id.parse::<i64>() {
+            Ok(id) if used.insert(id) => {
+                ids.insert(track.id.clone(), id);
+            }
+            _ => unnumbered.push(track),
+        }
+    }
+    let next = used.iter().max().map_or(1, |max| max + 1);
+    for (id, track) in (next..).zip(unnumbered) {
+        ids.insert(track.id.clone(), id);
+    }
+    ids
+}
+
+impl Library {
+    fn playlist_dictionary(&self, playlist: &Playlist, ids: &HashMap<TrackID, i64>) -> Dictionary {
+        let mut dict = Dictionary::new();
+        dict.insert("Name".to_owned(), Value::String(playlist.name.clone()));
+        if !playlist.description.is_empty() {
+            dict.insert(
+                "Description".to_owned(),
+                Value::String(playlist.description.clone()),
+            );
+        }
+        dict.insert(
+            "Playlist Persistent ID".to_owned(),
+            Value::String(playlist.persistent_id.clone()),
+        );
+        if !playlist.parent_persistent_id.is_empty() {
+            dict.insert(
+                "Parent Persistent ID".to_owned(),
+                Value::String(playlist.parent_persistent_id.clone()),
+            );
+        }
+        if playlist.folder {
+            dict.insert("Folder".to_owned(), Value::Boolean(true));
+        }
+        let items = playlist
+            .track_ids
+            .iter()
+            .filter_map(|id| ids.get(id))
+            .map(|&id| {
+                let mut item = Dictionary::new();
+                item.insert("Track ID".to_owned(), Value::Integer(id.into()));
+                Value::Dictionary(item)
+            })
+            .collect();
+        dict.insert("Playlist Items".to_owned(), Value::Array(items));
+        dict
+    }
+
+    /// The library as a property list, in the layout of the XML file written by Apple Music.
+    fn plist<'t>(&self, tracks: impl Iterator<Item = &'t Track>, playlists: &[&Playlist]) -> Value {
+        let mut dict = Dictionary::new();
+        dict.insert("Major Version".to_owned(), Value::Integer(1.into()));
+        dict.insert("Minor Version".to_owned(), Value::Integer(1.into()));
+        dict.insert("Date".to_owned(), date(self.date));
+        dict.insert(
+            "Application Version".to_owned(),
+            Value::String(
+                self.application_version
+                    .clone()
+                    .unwrap_or(env!("CARGO_PKG_VERSION").to_owned()),
+            ),
+        );
+        if let Some(folder) = &self.music_folder {
+            dict.insert("Music Folder".to_owned(), Value::String(folder.clone()));
+        }
+        if let Some(id) = &self.library_persistent_id {
+            dict.insert(
+                "Library Persistent ID".to_owned(),
+                Value::String(id.clone()),
+            );
+        }
+
+        let tracks: Vec<&Track> = tracks.collect();
+        let ids = track_ids(tracks.iter().copied());
+        let mut track_dict = Dictionary::new();
+        for track in tracks {
+            let id = ids[&track.id];
+            track_dict.insert(
+                id.to_string(),
+                Value::Dictionary(track_dictionary(track, id)),
+            );
+        }
+        dict.insert("Tracks".to_owned(), Value::Dictionary(track_dict));
+        dict.insert(
+            "Playlists".to_owned(),
+            Value::Array(
+                playlists
+                    .iter()
+                    .map(|p| Value::Dictionary(self.playlist_dictionary(p, &ids)))
+                    .collect(),
+            ),
+        );
+        Value::Dictionary(dict)
+    }
+
+    /// Writes the library as an XML property list, which can be read by [`Library::from_xml`].
+    pub fn xml_export(&self, path: &Path) -> Result<(), plist::Error> {
+        let playlists: Vec<&Playlist> = self.playlists.iter().collect();
+        self.plist(self.tracks.values(), &playlists)
+            .to_file_xml(path)
+    }
+
+    /// Writes a single playlist with its tracks, in the form Apple Music imports as a playlist.
+    pub fn playlist_xml_export(
+        &self,
+        playlist: &Playlist,
+        path: &Path,
+    ) -> Result<(), plist::Error> {
+        let tracks = playlist
+            .track_ids
+            .iter()
+            .filter_map(|id| self.tracks.get(id));
+        self.plist(tracks, &[playlist]).to_file_xml(path)
+    }
+}